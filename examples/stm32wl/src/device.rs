@@ -13,7 +13,6 @@ use lora_phy::LoRa;
 use lorawan::device::non_volatile_store::NonVolatileStore;
 use lorawan::device::{Device, DeviceSpecs};
 use lorawan::mac::types::Storable;
-use postcard::{from_bytes, to_slice};
 
 use crate::iv::{InterruptHandler, Stm32wlInterfaceVariant, SubghzSpiDevice};
 use crate::lora_radio::{LoraRadioKind, LoraType};
@@ -97,8 +96,7 @@ impl NonVolatileStore for DeviceNonVolatileStore<'_> {
         self.flash
             .blocking_erase(Self::offset(), Self::offset() + MAX_ERASE_SIZE as u32)
             .map_err(NonVolatileStoreError::Flash)?;
-        to_slice(&storable, self.buf.as_mut_slice())
-            .map_err(|_| NonVolatileStoreError::Encoding)?;
+        storable.encode(self.buf.as_mut_slice()).map_err(|_| NonVolatileStoreError::Encoding)?;
         self.flash.blocking_write(Self::offset(), &self.buf).map_err(NonVolatileStoreError::Flash)
     }
 
@@ -106,7 +104,7 @@ impl NonVolatileStore for DeviceNonVolatileStore<'_> {
         self.flash
             .blocking_read(Self::offset(), self.buf.as_mut_slice())
             .map_err(NonVolatileStoreError::Flash)?;
-        from_bytes(self.buf.as_mut_slice()).map_err(|_| NonVolatileStoreError::Encoding)
+        Storable::decode(self.buf.as_slice()).map_err(|_| NonVolatileStoreError::Encoding)
     }
 }
 
@@ -111,7 +111,15 @@ pub fn get_mac(device: &mut LoraDevice<'static>) -> Mac<EU868, DynamicChannelPla
         Ok(_) => defmt::info!("credentials and configuration loaded from non volatile"),
         Err(_) => defmt::info!("credentials and configuration not found in non volatile"),
     };
-    let (configuration, credentials) =
-        hydrate_res.unwrap_or((Default::default(), Credentials::new(app_eui, dev_eui, app_key)));
-    Mac::new(configuration, credentials)
+    let (configuration, credentials, session) = hydrate_res.unwrap_or((
+        Default::default(),
+        Credentials::new(app_eui, dev_eui, app_key),
+        None,
+    ));
+    let mut mac = Mac::new(configuration, credentials);
+    if let Some(session) = session {
+        defmt::info!("session restored from non volatile");
+        mac.restore_session(session);
+    }
+    mac
 }
@@ -28,4 +28,8 @@ impl ::lorawan::device::timer::Timer for LoraTimer {
         let fut = Timer::at(start + Duration::from_millis(millis)).await;
         Ok(fut)
     }
+
+    fn now(&self) -> u64 {
+        Instant::now().as_millis()
+    }
 }
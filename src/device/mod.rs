@@ -5,14 +5,17 @@ pub mod rng;
 pub mod timer;
 pub mod types;
 
-use lora_phy::mod_params::RadioError;
+use lora_phy::mod_params::{PacketStatus, RadioError};
 use lora_phy::mod_traits::RadioKind;
 use lora_phy::{DelayNs, LoRa};
 //use radio::Radio;
 use rng::Rng;
 use timer::Timer;
 
-use crate::mac::types::{Configuration, Credentials, Storable, DR};
+use self::types::{RfConfig, TxConfig};
+use crate::mac::duty_cycle::JoinBackOff;
+use crate::mac::region::any::RegionId;
+use crate::mac::types::{Configuration, Credentials, Session, Storable, DR};
 
 use self::non_volatile_store::NonVolatileStore;
 
@@ -64,6 +67,17 @@ pub trait DeviceSpecs {
         None
     }
 
+    /// Can the radio transmit and receive FSK modulation, used by data rates such as EU868 DR7?  If so,
+    /// [`Device::fsk_tx`] and [`Device::fsk_rx`] must be implemented.
+    fn supports_fsk() -> bool {
+        false
+    }
+    /// Can the radio transmit LR-FHSS modulation, used by data rates such as EU868 DR8 to DR11 (for example the
+    /// LR1110)?  If so, [`Device::lr_fhss_tx`] must be implemented.
+    fn supports_lr_fhss() -> bool {
+        false
+    }
+
     /// get Adaptive Data Rate acknowledge delay
     /// Set to None for default
     fn adr_ack_delay() -> Option<u8> {
@@ -99,8 +113,32 @@ pub trait Device: DeviceSpecs {
     fn rng(&mut self) -> &mut Self::Rng;
     /// Get the caller-supplied persistence implementation.
     fn non_volatile_store(&mut self) -> &mut Self::NonVolatileStore;
-    /// Process the DeviceTimeAns response from a network server as directed by the caller.
-    fn handle_device_time(&mut self, _seconds: u32, _nano_seconds: u32) {
+    /// Transmit a frame using the FSK modulation, frequency and power of `tx_config`, returning once the
+    /// transmission has ended.  lora-phy only drives LoRa modulation, so FSK is handled by the caller when
+    /// [`DeviceSpecs::supports_fsk`] is true.
+    async fn fsk_tx(&mut self, _tx_config: &TxConfig, _buf: &[u8]) -> Result<(), RadioError> {
+        Err(RadioError::InvalidConfiguration)
+    }
+    /// Transmit a frame using the LR-FHSS modulation, frequency and power of `tx_config`, returning once the
+    /// transmission has ended.  The frequency is the center of the occupied channel.  Called when
+    /// [`DeviceSpecs::supports_lr_fhss`] is true.
+    async fn lr_fhss_tx(&mut self, _tx_config: &TxConfig, _buf: &[u8]) -> Result<(), RadioError> {
+        Err(RadioError::InvalidConfiguration)
+    }
+    /// Receive a frame using the FSK modulation and frequency of `rf_config`, giving up if no preamble is detected
+    /// within `window_ms` milliseconds, or listening until a frame is received if None.
+    async fn fsk_rx(
+        &mut self,
+        _rf_config: &RfConfig,
+        _window_ms: Option<u32>,
+        _buf: &mut [u8],
+    ) -> Result<(u8, PacketStatus), RadioError> {
+        Err(RadioError::InvalidConfiguration)
+    }
+    /// Process the DeviceTimeAns response from a network server as directed by the caller, given the current GPS
+    /// time in milliseconds since the GPS epoch (1980-01-06 00:00:00 UTC), corrected for the time elapsed since
+    /// the end of the uplink carrying the DeviceTimeReq.
+    fn handle_device_time(&mut self, _gps_time_ms: u64) {
         // default do nothing
     }
     /// Process the LinkCheckAns response from a network server as directed by the caller.
@@ -117,20 +155,59 @@ pub trait Device: DeviceSpecs {
     fn adaptive_data_rate_enabled(&self) -> bool {
         true
     }
+    /// Get the number of uplink frame counter increments which may pass before the uplink frame counter is
+    /// persisted again, bounding non-volatile storage wear.  On restoration, the uplink frame counter is advanced by
+    /// this amount so that no frame counter is reused.  The downlink frame counter is persisted whenever it changes.
+    fn frame_counter_persist_interval(&self) -> u32 {
+        32
+    }
     /// Persist information required to maintain communication with a network server through end device power cycles.
     fn persist_to_non_volatile(
         &mut self,
         configuration: &Configuration,
         credentials: &Credentials,
+        session: Option<&Session>,
     ) -> Result<(), <Self::NonVolatileStore as NonVolatileStore>::Error> {
-        let storable = Storable {
+        let mut storable = Storable {
             rx1_data_rate_offset: configuration.rx1_data_rate_offset,
             rx_delay: configuration.rx_delay,
             rx2_data_rate: configuration.rx2_data_rate,
             rx2_frequency: configuration.rx2_frequency,
             dev_nonce: credentials.dev_nonce,
+            nwkskey: session.map(|session| session.nwkskey().inner().0),
+            appskey: session.map(|session| session.appskey().inner().0),
+            devaddr: session.and_then(|session| session.devaddr().as_ref().try_into().ok()),
+            fcnt_up: session.map(|session| session.fcnt_up).unwrap_or_default(),
+            fcnt_down: session.map(|session| session.fcnt_down).unwrap_or_default(),
+            adr_ack_cnt: session.map(|session| session.adr_ack_cnt).unwrap_or_default(),
+            tx_power: configuration.tx_power,
+            tx_data_rate: configuration.tx_data_rate,
+            number_of_transmissions: configuration.number_of_transmissions,
+            uplink_dwell_time: configuration.uplink_dwell_time,
+            downlink_dwell_time: configuration.downlink_dwell_time,
+            max_eirp: configuration.max_eirp,
+            channel_plan_variant: session.and_then(|session| session.channel_plan_variant),
+            channel_mask: session.and_then(|session| session.channel_mask),
+            region: configuration.region.map(|region| region as u8),
+            join_elapsed: configuration
+                .join_back_off
+                .elapsed
+                .map(|elapsed| (elapsed / 1000).min(u32::MAX as u64) as u32),
+            join_time_off: configuration.join_back_off.time_off.div_ceil(1000) as u32,
         };
         if let Ok(old_storable) = self.non_volatile_store().load() {
+            let interval = self.frame_counter_persist_interval();
+            let within_interval =
+                |new: u32, old: u32| new >= old && new < old.saturating_add(interval);
+            // the downlink frame counter is always written, so replayed downlinks are rejected after a power cycle
+            if storable.devaddr == old_storable.devaddr
+                && within_interval(storable.fcnt_up, old_storable.fcnt_up)
+                && storable.fcnt_down == old_storable.fcnt_down
+            {
+                // defer uplink frame counter updates until the interval is reached
+                storable.fcnt_up = old_storable.fcnt_up;
+                storable.adr_ack_cnt = old_storable.adr_ack_cnt;
+            }
             if storable != old_storable {
                 trace!("overwrite {} {}", old_storable, storable);
                 self.non_volatile_store().save(storable)?;
@@ -144,24 +221,51 @@ pub trait Device: DeviceSpecs {
         Ok(())
     }
 
-    /// Restore information required to maintain end device communication with a network server.
+    /// Restore information required to maintain end device communication with a network server, including the
+    /// session if one was established.  Pass the session to [`Mac::restore_session`](crate::mac::Mac::restore_session)
+    /// to continue without a new join.  A region selected through
+    /// [`AnyRegion::select`](crate::mac::region::any::AnyRegion::select) is given by [`Configuration::region`];
+    /// select it again before creating a `Mac` for `AnyRegion`.
     fn hydrate_from_non_volatile(
         &mut self,
         app_eui: [u8; 8],
         dev_eui: [u8; 8],
         app_key: [u8; 16],
-    ) -> Result<(Configuration, Credentials), <Self::NonVolatileStore as NonVolatileStore>::Error>
-    {
+    ) -> Result<
+        (Configuration, Credentials, Option<Session>),
+        <Self::NonVolatileStore as NonVolatileStore>::Error,
+    > {
         let storable: Storable = self.non_volatile_store().load()?;
         let configuration = Configuration {
             rx1_data_rate_offset: storable.rx1_data_rate_offset,
             rx_delay: storable.rx_delay,
             rx2_data_rate: storable.rx2_data_rate,
             rx2_frequency: storable.rx2_frequency,
+            tx_power: storable.tx_power,
+            tx_data_rate: storable.tx_data_rate,
+            number_of_transmissions: storable.number_of_transmissions.max(1),
+            uplink_dwell_time: storable.uplink_dwell_time,
+            downlink_dwell_time: storable.downlink_dwell_time,
+            max_eirp: storable.max_eirp,
+            join_back_off: JoinBackOff::restore(storable.join_elapsed, storable.join_time_off),
+            region: storable.region.and_then(|region| RegionId::try_from(region).ok()),
             ..Default::default()
         };
         let mut credentials = Credentials::new(app_eui, dev_eui, app_key);
         credentials.dev_nonce = storable.dev_nonce;
-        Ok((configuration, credentials))
+        let session = match (storable.nwkskey, storable.appskey, storable.devaddr) {
+            (Some(nwkskey), Some(appskey), Some(devaddr)) => {
+                let mut session = Session::new(nwkskey.into(), appskey.into(), devaddr.into());
+                session.fcnt_up =
+                    storable.fcnt_up.saturating_add(self.frame_counter_persist_interval());
+                session.fcnt_down = storable.fcnt_down;
+                session.adr_ack_cnt = storable.adr_ack_cnt;
+                session.channel_plan_variant = storable.channel_plan_variant;
+                session.channel_mask = storable.channel_mask;
+                Some(session)
+            }
+            _ => None,
+        };
+        Ok((configuration, credentials, session))
     }
 }
@@ -2,7 +2,8 @@
 
 use core::fmt::Debug;
 
-use crate::mac::types::Storable;
+use crate::mac::class_b::crc16;
+use crate::mac::types::{Storable, DR};
 
 /// Specification of the functionality required of the caller for persistence.
 pub trait NonVolatileStore {
@@ -13,8 +14,317 @@ pub trait NonVolatileStore {
     #[cfg(not(feature = "defmt"))]
     type Error: Debug;
 
-    /// Save storable to persistent store.
+    /// Save storable to persistent store, for example as encoded by [`Storable::encode`].
     fn save(&mut self, storable: Storable) -> Result<(), Self::Error>;
-    /// Load storable from peristent store.
+    /// Load storable from peristent store, for example as decoded by [`Storable::decode`].
     fn load(&mut self) -> Result<Storable, Self::Error>;
 }
+
+/// Marker identifying an encoded [`Storable`] record.
+const MAGIC: [u8; 2] = *b"LW";
+/// Version of the current record layout.  Fields are only appended within a version, so records written by
+/// older or newer firmware using the same version remain readable; the version is bumped only when the layout
+/// changes incompatibly, with a decoder retained for each earlier version to migrate its records.
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 2;
+const CRC_LEN: usize = 2;
+
+/// Possible errors encoding or decoding a [`Storable`] record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum StorableError {
+    BufferTooSmall,
+    InvalidHeader,
+    InvalidCrc,
+    UnsupportedVersion(u8),
+    InvalidLegacyRecord,
+}
+
+impl Storable {
+    /// Maximum size of an encoded record.
+    pub const MAX_ENCODED_LEN: usize = 128;
+
+    /// Encode into a versioned record protected by a CRC, returning the number of bytes used.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, StorableError> {
+        let mut writer = Writer { buf, pos: HEADER_LEN };
+        writer.option(self.rx1_data_rate_offset.map(|offset| [offset]))?;
+        writer.option(self.rx_delay.map(|delay| [delay]))?;
+        writer.option(self.rx2_data_rate.map(|dr| [dr as u8]))?;
+        writer.option(self.rx2_frequency.map(u32::to_le_bytes))?;
+        writer.bytes(&self.dev_nonce.to_le_bytes())?;
+        writer.option(self.nwkskey)?;
+        writer.option(self.appskey)?;
+        writer.option(self.devaddr)?;
+        writer.bytes(&self.fcnt_up.to_le_bytes())?;
+        writer.bytes(&self.fcnt_down.to_le_bytes())?;
+        writer.bytes(&[self.adr_ack_cnt])?;
+        writer.option(self.tx_power.map(i8::to_le_bytes))?;
+        writer.option(self.tx_data_rate.map(|dr| [dr as u8]))?;
+        writer.bytes(&[self.number_of_transmissions])?;
+        writer.option(self.uplink_dwell_time.map(|dwell_time| [dwell_time as u8]))?;
+        writer.option(self.downlink_dwell_time.map(|dwell_time| [dwell_time as u8]))?;
+        writer.option(self.max_eirp.map(i8::to_le_bytes))?;
+        writer.option(self.channel_plan_variant.map(|variant| [variant]))?;
+        writer.option(self.region.map(|region| [region]))?;
+        writer.option(self.join_elapsed.map(u32::to_le_bytes))?;
+        writer.bytes(&self.join_time_off.to_le_bytes())?;
+        writer.option(self.channel_mask)?;
+        let Writer { buf, pos } = writer;
+        buf[..MAGIC.len()].copy_from_slice(&MAGIC);
+        buf[MAGIC.len()] = VERSION;
+        buf[MAGIC.len() + 1..HEADER_LEN]
+            .copy_from_slice(&((pos - HEADER_LEN) as u16).to_le_bytes());
+        if buf.len() < pos + CRC_LEN {
+            return Err(StorableError::BufferTooSmall);
+        }
+        let crc = crc16(&buf[..pos]);
+        buf[pos..pos + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        Ok(pos + CRC_LEN)
+    }
+
+    /// Decode a record written by [`Storable::encode`], migrating records written by earlier firmware.  Data
+    /// following the record, such as erased flash, is ignored.
+    pub fn decode(data: &[u8]) -> Result<Self, StorableError> {
+        if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+            return decode_legacy(data);
+        }
+        if data.len() < HEADER_LEN {
+            return Err(StorableError::InvalidHeader);
+        }
+        let version = data[MAGIC.len()];
+        let len = u16::from_le_bytes([data[MAGIC.len() + 1], data[MAGIC.len() + 2]]) as usize;
+        let end = HEADER_LEN + len;
+        if data.len() < end + CRC_LEN {
+            return Err(StorableError::InvalidHeader);
+        }
+        if crc16(&data[..end]) != u16::from_le_bytes([data[end], data[end + 1]]) {
+            return Err(StorableError::InvalidCrc);
+        }
+        let payload = &data[HEADER_LEN..end];
+        match version {
+            1 => Ok(decode_v1(payload)),
+            _ => Err(StorableError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Decode the current layout.  Fields missing from a record written before they were added keep their defaults,
+/// and fields appended by newer firmware are ignored.
+fn decode_v1(payload: &[u8]) -> Storable {
+    let mut storable = Storable::default();
+    let mut reader = Reader { data: payload };
+    let _ = (|| -> Option<()> {
+        storable.rx1_data_rate_offset = reader.option::<1>()?.map(|[offset]| offset);
+        storable.rx_delay = reader.option::<1>()?.map(|[delay]| delay);
+        storable.rx2_data_rate = reader.option::<1>()?.and_then(|[dr]| DR::try_from(dr).ok());
+        storable.rx2_frequency = reader.option()?.map(u32::from_le_bytes);
+        storable.dev_nonce = u16::from_le_bytes(reader.bytes()?);
+        storable.nwkskey = reader.option()?;
+        storable.appskey = reader.option()?;
+        storable.devaddr = reader.option()?;
+        storable.fcnt_up = u32::from_le_bytes(reader.bytes()?);
+        storable.fcnt_down = u32::from_le_bytes(reader.bytes()?);
+        storable.adr_ack_cnt = reader.bytes::<1>()?[0];
+        storable.tx_power = reader.option()?.map(i8::from_le_bytes);
+        storable.tx_data_rate = reader.option::<1>()?.and_then(|[dr]| DR::try_from(dr).ok());
+        storable.number_of_transmissions = reader.bytes::<1>()?[0];
+        storable.uplink_dwell_time = reader.option::<1>()?.map(|[dwell_time]| dwell_time != 0);
+        storable.downlink_dwell_time = reader.option::<1>()?.map(|[dwell_time]| dwell_time != 0);
+        storable.max_eirp = reader.option()?.map(i8::from_le_bytes);
+        storable.channel_plan_variant = reader.option::<1>()?.map(|[variant]| variant);
+        storable.region = reader.option::<1>()?.map(|[region]| region);
+        storable.join_elapsed = reader.option()?.map(u32::from_le_bytes);
+        storable.join_time_off = u32::from_le_bytes(reader.bytes()?);
+        storable.channel_mask = reader.option()?;
+        Some(())
+    })();
+    storable
+}
+
+/// Decode the unversioned postcard encoding of the original layout (RX settings and DevNonce only), as written
+/// through serde by earlier firmware, so the DevNonce survives the upgrade.
+fn decode_legacy(data: &[u8]) -> Result<Storable, StorableError> {
+    let mut reader = Reader { data };
+    let mut legacy = || -> Option<Storable> {
+        let rx1_data_rate_offset = reader.legacy_option(|reader| Some(reader.bytes::<1>()?[0]))?;
+        let rx_delay = reader.legacy_option(|reader| Some(reader.bytes::<1>()?[0]))?;
+        let rx2_data_rate = reader
+            .legacy_option(|reader| DR::try_from(u8::try_from(reader.varint()?).ok()?).ok())?;
+        let rx2_frequency = reader.legacy_option(|reader| u32::try_from(reader.varint()?).ok())?;
+        let dev_nonce = u16::try_from(reader.varint()?).ok()?;
+        Some(Storable {
+            rx1_data_rate_offset,
+            rx_delay,
+            rx2_data_rate,
+            rx2_frequency,
+            dev_nonce,
+            number_of_transmissions: 1,
+            ..Default::default()
+        })
+    };
+    legacy().ok_or(StorableError::InvalidLegacyRecord)
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), StorableError> {
+        let end = self.pos + bytes.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(StorableError::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn option<const N: usize>(&mut self, value: Option<[u8; N]>) -> Result<(), StorableError> {
+        match value {
+            Some(bytes) => {
+                self.bytes(&[1])?;
+                self.bytes(&bytes)
+            }
+            None => self.bytes(&[0]),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*bytes)
+    }
+
+    fn option<const N: usize>(&mut self) -> Option<Option<[u8; N]>> {
+        match self.bytes::<1>()? {
+            [0] => Some(None),
+            _ => self.bytes().map(Some),
+        }
+    }
+
+    fn legacy_option<T>(
+        &mut self,
+        value: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<Option<T>> {
+        match self.bytes::<1>()? {
+            [0] => Some(None),
+            [1] => value(self).map(Some),
+            _ => None,
+        }
+    }
+
+    /// Read a postcard (LEB128) variable-length integer.
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let [byte] = self.bytes::<1>()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storable() -> Storable {
+        Storable {
+            rx1_data_rate_offset: Some(2),
+            rx_delay: Some(5),
+            rx2_data_rate: Some(DR::_3),
+            rx2_frequency: Some(869_525_000),
+            dev_nonce: 300,
+            nwkskey: Some([1u8; 16]),
+            appskey: Some([2u8; 16]),
+            devaddr: Some([1, 2, 3, 4]),
+            fcnt_up: 70_000,
+            fcnt_down: 12,
+            adr_ack_cnt: 3,
+            tx_power: Some(-2),
+            tx_data_rate: Some(DR::_5),
+            number_of_transmissions: 2,
+            uplink_dwell_time: Some(true),
+            downlink_dwell_time: Some(false),
+            max_eirp: Some(16),
+            channel_plan_variant: Some(3),
+            region: Some(1),
+            join_elapsed: Some(7_200),
+            join_time_off: 100,
+            channel_mask: Some([0, 0, 0, 0, 0, 0, 0xFF, 0, 0x40]),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = [0xFFu8; 256];
+        let len = storable().encode(&mut buf).unwrap();
+        assert!(len <= Storable::MAX_ENCODED_LEN);
+        assert_eq!(Storable::decode(&buf), Ok(storable()));
+        assert_eq!(storable().encode(&mut buf[..len - 1]), Err(StorableError::BufferTooSmall));
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut buf = [0xFFu8; 256];
+        let len = storable().encode(&mut buf).unwrap();
+        buf[len - 3] ^= 0x01;
+        assert_eq!(Storable::decode(&buf), Err(StorableError::InvalidCrc));
+        let len = storable().encode(&mut buf).unwrap();
+        buf[2] = VERSION + 1;
+        let crc = crc16(&buf[..len - CRC_LEN]);
+        buf[len - CRC_LEN..len].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(Storable::decode(&buf), Err(StorableError::UnsupportedVersion(VERSION + 1)));
+        // erased flash
+        assert_eq!(Storable::decode(&[0xFF; 256]), Err(StorableError::InvalidLegacyRecord));
+    }
+
+    #[test]
+    fn appended_fields() {
+        let mut buf = [0xFFu8; 256];
+        let len = storable().encode(&mut buf).unwrap();
+        // a record written before the ADR state, the TXParamSetupReq settings, the channel plan variant, the
+        // region, the join back-off and the channel mask were added
+        let short_len = len - CRC_LEN - 35;
+        let mut short = [0u8; 256];
+        short[..short_len].copy_from_slice(&buf[..short_len]);
+        short[3..5].copy_from_slice(&((short_len - HEADER_LEN) as u16).to_le_bytes());
+        let crc = crc16(&short[..short_len]);
+        short[short_len..short_len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        let decoded = Storable::decode(&short).unwrap();
+        assert_eq!(decoded.fcnt_down, 12);
+        assert_eq!(decoded.adr_ack_cnt, 0);
+        assert_eq!(decoded.tx_data_rate, None);
+        assert_eq!(decoded.number_of_transmissions, 0);
+        assert_eq!(decoded.max_eirp, None);
+        assert_eq!(decoded.region, None);
+        assert_eq!(decoded.join_elapsed, None);
+        assert_eq!(decoded.join_time_off, 0);
+        assert_eq!(decoded.channel_mask, None);
+    }
+
+    #[test]
+    fn legacy() {
+        let legacy = [1, 2, 1, 5, 1, 3, 1, 136, 204, 207, 158, 3, 172, 2, 0xFF, 0xFF];
+        let decoded = Storable::decode(&legacy).unwrap();
+        assert_eq!(decoded.rx1_data_rate_offset, Some(2));
+        assert_eq!(decoded.rx_delay, Some(5));
+        assert_eq!(decoded.rx2_data_rate, Some(DR::_3));
+        assert_eq!(decoded.rx2_frequency, Some(869_525_000));
+        assert_eq!(decoded.dev_nonce, 300);
+        assert_eq!(decoded.devaddr, None);
+        assert_eq!(Storable::decode(&[0, 0, 0, 0, 0x80]), Err(StorableError::InvalidLegacyRecord));
+    }
+}
@@ -15,4 +15,8 @@ pub trait Timer: Sized {
     fn reset(&mut self);
     /// Set the timer to notify in the future.
     async fn at(&self, millis: u64) -> Result<(), Self::Error>;
+    /// Get the number of milliseconds elapsed since a fixed point in the past, unaffected by reset.
+    /// Used to schedule Class B beacon and ping slot receive windows, and to track duty cycle time-off and network
+    /// time.
+    fn now(&self) -> u64;
 }
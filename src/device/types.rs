@@ -1,6 +1,6 @@
 //! Types used to control communication with the LoRa physical layer.
 
-use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
+use lora_modulation::{Bandwidth, BaseBandModulationParams, CodingRate, SpreadingFactor};
 
 /// LoRaWAN radio signal configuration.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -10,18 +10,68 @@ pub struct RfConfig {
     pub frequency: u32,
     /// Coding rate (ratio of actual data bits to error-correcting data bits).
     pub coding_rate: CodingRate,
-    /// Data rate (bandwidth and spreading factor).
+    /// Data rate (modulation, with bandwidth and spreading factor for LoRa).
     pub data_rate: Datarate,
 }
 
+impl RfConfig {
+    /// Get the LoRa modulation parameters, or None for FSK or LR-FHSS modulation.
+    pub fn lora_modulation_params(&self) -> Option<BaseBandModulationParams> {
+        match self.data_rate {
+            Datarate::LoRa { spreading_factor, bandwidth } => {
+                Some(BaseBandModulationParams::new(spreading_factor, bandwidth, self.coding_rate))
+            }
+            Datarate::Fsk { .. } | Datarate::LrFhss { .. } => None,
+        }
+    }
+}
+
 /// LoRaWAN data rate.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone)]
-pub struct Datarate {
-    /// Bandwidth.
-    pub bandwidth: Bandwidth,
-    /// Spreading factor.
-    pub spreading_factor: SpreadingFactor,
+pub enum Datarate {
+    /// LoRa modulation.
+    LoRa {
+        /// Bandwidth.
+        bandwidth: Bandwidth,
+        /// Spreading factor.
+        spreading_factor: SpreadingFactor,
+    },
+    /// FSK modulation with the LoRaWAN settings: GFSK with a 0.5 Gaussian filter and a frequency deviation of
+    /// half the bit rate, a 5 byte preamble, the 3 byte sync word 0xC194C1, a length byte, whitening and CRC-16.
+    Fsk {
+        /// Bit rate in bits per second.
+        bit_rate: u32,
+    },
+    /// LR-FHSS modulation, used for uplinks only.
+    LrFhss {
+        /// Coding rate.
+        coding_rate: LrFhssCodingRate,
+        /// Occupied channel width in Hz.
+        bandwidth: u32,
+        /// Minimum separation of the hopping channels in Hz.
+        grid: u32,
+    },
+}
+
+/// LR-FHSS coding rate.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LrFhssCodingRate {
+    /// Coding rate 1/3, sending 3 headers.
+    _1_3,
+    /// Coding rate 2/3, sending 2 headers.
+    _2_3,
+}
+
+impl LrFhssCodingRate {
+    /// Get the number of headers repeated at the start of each frame.
+    pub fn header_count(self) -> u32 {
+        match self {
+            LrFhssCodingRate::_1_3 => 3,
+            LrFhssCodingRate::_2_3 => 2,
+        }
+    }
 }
 
 /// LoRaWAN packet transmission configuration.
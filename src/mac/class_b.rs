@@ -0,0 +1,342 @@
+//! Class B beacon tracking and ping slot scheduling.
+
+use encoding::default_crypto::DefaultFactory;
+use encoding::keys::{CryptoFactory, Encrypter, AES128};
+use heapless::Vec;
+
+use super::types::DR;
+
+/// Duration of a beacon period in milliseconds.
+pub const BEACON_PERIOD_MS: u64 = 128_000;
+/// Duration reserved at the start of a beacon period for the beacon itself.
+pub const BEACON_RESERVED_MS: u64 = 2_120;
+/// Duration of the beacon window in which ping slots are located.
+pub const BEACON_WINDOW_MS: u64 = 122_880;
+/// Duration of a ping slot.
+pub const PING_SLOT_LEN_MS: u64 = 30;
+/// Default ping slot periodicity (one ping slot per beacon period).
+pub const DEFAULT_PING_SLOT_PERIODICITY: u8 = 7;
+/// Number of consecutive beacons which may be missed before Class B operation is abandoned (about two hours).
+pub const MAX_MISSED_BEACONS: u8 = 56;
+
+pub(crate) const BEACON_PREAMBLE_SYMBOLS: u16 = 10;
+/// Amount by which the beacon receive window is opened early and widened per missed beacon.
+pub(crate) const BEACON_WINDOW_WIDENING_MS: u64 = 20;
+
+const CLASS_B_CIDS: core::ops::RangeInclusive<u8> = 0x10..=0x13;
+/// Maximum number of Class B MAC commands handled from a single downlink.
+const MAX_CLASS_B_CMDS: usize = 8;
+
+/// Content of a received network beacon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Beacon {
+    /// GPS time in seconds (modulo 2^32) at the start of the beacon transmission.
+    pub time: u32,
+    /// Gateway-specific information (InfoDesc followed by the gateway coordinates).
+    pub gw_specific: [u8; 7],
+}
+
+impl Beacon {
+    /// Size of the beacon payload given the region's leading and trailing RFU field lengths.
+    pub fn size(rfu_lengths: (usize, usize)) -> usize {
+        rfu_lengths.0 + 4 + 2 + 7 + rfu_lengths.1 + 2
+    }
+
+    /// Parse a beacon payload, checking both CRCs.
+    pub fn parse(data: &[u8], rfu_lengths: (usize, usize)) -> Option<Self> {
+        if data.len() != Self::size(rfu_lengths) {
+            return None;
+        }
+        let (common, gw) = data.split_at(rfu_lengths.0 + 4 + 2);
+        let time_crc = u16::from_le_bytes([common[common.len() - 2], common[common.len() - 1]]);
+        if crc16(&common[..common.len() - 2]) != time_crc {
+            return None;
+        }
+        let gw_crc = u16::from_le_bytes([gw[gw.len() - 2], gw[gw.len() - 1]]);
+        if crc16(&gw[..gw.len() - 2]) != gw_crc {
+            return None;
+        }
+        let time_index = rfu_lengths.0;
+        let time = u32::from_le_bytes([
+            common[time_index],
+            common[time_index + 1],
+            common[time_index + 2],
+            common[time_index + 3],
+        ]);
+        let mut gw_specific = [0u8; 7];
+        gw_specific.copy_from_slice(&gw[..7]);
+        Some(Self { time, gw_specific })
+    }
+}
+
+/// CRC-16 (polynomial 0x1021, initial value 0) used to protect beacon fields.
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Number of ping slots between two consecutive ping slots of the end device.
+pub fn ping_period(periodicity: u8) -> u16 {
+    1 << (5 + periodicity.min(7))
+}
+
+/// Compute the pseudo-random ping slot offset for the beacon period starting at `beacon_time`.
+pub fn ping_offset(beacon_time: u32, dev_addr: [u8; 4], periodicity: u8) -> u16 {
+    let mut block = [0u8; 16];
+    block[..4].copy_from_slice(&beacon_time.to_le_bytes());
+    block[4..8].copy_from_slice(&dev_addr);
+    let encrypter = DefaultFactory.new_enc(&AES128([0u8; 16]));
+    encrypter.encrypt_block((&mut block[..]).into());
+    (block[0] as u16 + (block[1] as u16) * 256) % ping_period(periodicity)
+}
+
+/// Get the index of the channel used for the beacon sent at `beacon_time` (`dev_addr` zero), or for the ping slots
+/// of an end device in the beacon period starting at `beacon_time`.
+pub(crate) fn channel_index(beacon_time: u32, dev_addr: u32, channels: u8) -> u8 {
+    ((dev_addr as u64 + (beacon_time as u64 / (BEACON_PERIOD_MS / 1000))) % channels as u64) as u8
+}
+
+/// Timing of the beacon period the end device is currently tracking.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct BeaconState {
+    /// GPS time in seconds at the start of the beacon period.
+    pub(crate) time: u32,
+    /// Timer reading in milliseconds at the start of the beacon period.
+    pub(crate) period_start: u64,
+    /// Number of consecutive beacons missed.
+    pub(crate) missed: u8,
+}
+
+impl BeaconState {
+    /// Get the start of the next ping slot at or after `now`, or None if the beacon window is exhausted.
+    pub(crate) fn next_ping_slot(
+        &self,
+        now: u64,
+        ping_offset: u16,
+        periodicity: u8,
+    ) -> Option<u64> {
+        let slot_spacing = ping_period(periodicity) as u64 * PING_SLOT_LEN_MS;
+        let window_start = self.period_start + BEACON_RESERVED_MS;
+        let first = window_start + ping_offset as u64 * PING_SLOT_LEN_MS;
+        let next = if now <= first {
+            first
+        } else {
+            first + (now - first).div_ceil(slot_spacing) * slot_spacing
+        };
+        if next + PING_SLOT_LEN_MS <= window_start + BEACON_WINDOW_MS {
+            Some(next)
+        } else {
+            None
+        }
+    }
+}
+
+/// Expected arrival of the next beacon as indicated by a BeaconTimingAns.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct BeaconTiming {
+    pub(crate) at: u64,
+    pub(crate) channel: u8,
+}
+
+/// Class B properties maintained by the MAC.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct ClassB {
+    pub(crate) periodicity: Option<u8>,
+    pub(crate) pending_periodicity: Option<u8>,
+    pub(crate) ping_slot_frequency: Option<u32>,
+    pub(crate) ping_slot_data_rate: Option<DR>,
+    pub(crate) beacon_frequency: Option<u32>,
+    pub(crate) beacon_timing: Option<BeaconTiming>,
+    pub(crate) beacon: Option<BeaconState>,
+    pub(crate) uplink_cmds: Vec<UplinkCommand, 4>,
+}
+
+impl ClassB {
+    pub(crate) fn periodicity(&self) -> u8 {
+        self.periodicity.unwrap_or(DEFAULT_PING_SLOT_PERIODICITY)
+    }
+}
+
+/// Class B MAC commands sent from the network server to the end device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum DownlinkCommand {
+    PingSlotInfoAns,
+    PingSlotChannelReq { frequency: u32, data_rate: u8 },
+    BeaconTimingAns { delay: u16, channel: u8 },
+    BeaconFreqReq { frequency: u32 },
+}
+
+/// Class B MAC commands sent from the end device to the network server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum UplinkCommand {
+    PingSlotInfoReq { periodicity: u8 },
+    PingSlotChannelAns { frequency_ack: bool, data_rate_ack: bool },
+    BeaconTimingReq,
+    BeaconFreqAns { frequency_ack: bool },
+}
+
+impl UplinkCommand {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            UplinkCommand::BeaconTimingReq => 1,
+            _ => 2,
+        }
+    }
+
+    /// Serialize the command into `buf`, returning the number of bytes written.
+    pub(crate) fn build(&self, buf: &mut [u8]) -> usize {
+        let (cid, payload) = match self {
+            UplinkCommand::PingSlotInfoReq { periodicity } => (0x10, Some(periodicity & 0x07)),
+            UplinkCommand::PingSlotChannelAns { frequency_ack, data_rate_ack } => {
+                (0x11, Some(*frequency_ack as u8 | (*data_rate_ack as u8) << 1))
+            }
+            UplinkCommand::BeaconTimingReq => (0x12, None),
+            UplinkCommand::BeaconFreqAns { frequency_ack } => (0x13, Some(*frequency_ack as u8)),
+        };
+        buf[0] = cid;
+        if let Some(payload) = payload {
+            buf[1] = payload;
+        }
+        self.len()
+    }
+}
+
+/// Get the payload length of a downlink MAC command, or None if the CID is unknown.
+fn downlink_payload_len(cid: u8) -> Option<usize> {
+    match cid {
+        0x02 => Some(2), // LinkCheckAns
+        0x03 => Some(4), // LinkADRReq
+        0x04 => Some(1), // DutyCycleReq
+        0x05 => Some(4), // RXParamSetupReq
+        0x06 => Some(0), // DevStatusReq
+        0x07 => Some(5), // NewChannelReq
+        0x08 => Some(1), // RXTimingSetupReq
+        0x09 => Some(1), // TXParamSetupReq
+        0x0A => Some(4), // DlChannelReq
+        0x0D => Some(5), // DeviceTimeAns
+        0x10 => Some(0), // PingSlotInfoAns
+        0x11 => Some(4), // PingSlotChannelReq
+        0x12 => Some(3), // BeaconTimingAns
+        0x13 => Some(3), // BeaconFreqReq
+        _ => None,
+    }
+}
+
+fn frequency(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16) * 100
+}
+
+/// Separate the Class B commands from a sequence of downlink MAC commands, which the encoding crate does not parse.
+/// The remaining commands are copied to `other_cmds` and their length returned. Processing stops at an unknown CID,
+/// since the length of its payload, and therefore the start of any following command, cannot be determined.
+pub(crate) fn split_commands(
+    cmds: &[u8],
+    other_cmds: &mut [u8],
+) -> (usize, Vec<DownlinkCommand, MAX_CLASS_B_CMDS>) {
+    let mut class_b_cmds = Vec::new();
+    let mut index = 0;
+    let mut len = 0;
+    while index < cmds.len() {
+        let cid = cmds[index];
+        let Some(payload_len) = downlink_payload_len(cid) else {
+            break;
+        };
+        let end = index + 1 + payload_len;
+        if end > cmds.len() {
+            break;
+        }
+        let payload = &cmds[index + 1..end];
+        if CLASS_B_CIDS.contains(&cid) {
+            let cmd = match cid {
+                0x10 => DownlinkCommand::PingSlotInfoAns,
+                0x11 => DownlinkCommand::PingSlotChannelReq {
+                    frequency: frequency(&payload[..3]),
+                    data_rate: payload[3] & 0x0F,
+                },
+                0x12 => DownlinkCommand::BeaconTimingAns {
+                    delay: u16::from_le_bytes([payload[0], payload[1]]),
+                    channel: payload[2],
+                },
+                _ => DownlinkCommand::BeaconFreqReq { frequency: frequency(payload) },
+            };
+            if class_b_cmds.push(cmd).is_err() {
+                warn!("too many class B commands");
+            }
+        } else if len + end - index <= other_cmds.len() {
+            other_cmds[len..len + end - index].copy_from_slice(&cmds[index..end]);
+            len += end - index;
+        }
+        index = end;
+    }
+    (len, class_b_cmds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn parse_beacon() {
+        let data = [0, 0, 128, 2, 150, 73, 248, 216, 0, 1, 2, 3, 4, 5, 6, 12, 217];
+        let beacon = Beacon::parse(&data, (2, 0)).unwrap();
+        assert_eq!(beacon.time, 1234567808);
+        assert_eq!(beacon.gw_specific, [0, 1, 2, 3, 4, 5, 6]);
+
+        let mut corrupted = data;
+        corrupted[3] ^= 0x01;
+        assert!(Beacon::parse(&corrupted, (2, 0)).is_none());
+        assert!(Beacon::parse(&data, (5, 3)).is_none());
+    }
+
+    #[test]
+    fn ping_slots() {
+        assert_eq!(ping_period(0), 32);
+        assert_eq!(ping_period(7), 4096);
+        assert_eq!(ping_offset(1234567808, [0x01, 0x02, 0x03, 0x04], 7), 951);
+        assert_eq!(ping_offset(1234567808, [0x01, 0x02, 0x03, 0x04], 0), 23);
+
+        let beacon = BeaconState { time: 1234567808, period_start: 1000, missed: 0 };
+        let first = 1000 + BEACON_RESERVED_MS + 23 * PING_SLOT_LEN_MS;
+        assert_eq!(beacon.next_ping_slot(0, 23, 0), Some(first));
+        assert_eq!(beacon.next_ping_slot(first, 23, 0), Some(first));
+        assert_eq!(beacon.next_ping_slot(first + 1, 23, 0), Some(first + 32 * PING_SLOT_LEN_MS));
+        assert_eq!(beacon.next_ping_slot(first + 1, 23, 7), None);
+    }
+
+    #[test]
+    fn split_class_b_commands() {
+        // PingSlotChannelReq (869.525 MHz, DR3), DevStatusReq, BeaconFreqReq (869.525 MHz)
+        let cmds = [0x11, 0xD2, 0xAD, 0x84, 0x03, 0x06, 0x13, 0xD2, 0xAD, 0x84];
+        let mut other_cmds = [0u8; 15];
+        let (len, class_b_cmds) = split_commands(&cmds, &mut other_cmds);
+        assert_eq!(&other_cmds[..len], &[0x06]);
+        assert_eq!(
+            class_b_cmds.as_slice(),
+            &[
+                DownlinkCommand::PingSlotChannelReq { frequency: 869_525_000, data_rate: 3 },
+                DownlinkCommand::BeaconFreqReq { frequency: 869_525_000 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Tracking of the transmission time-off imposed by regional sub-band duty cycles and by the network server.
+
+use core::cmp::max;
+
+/// Maximum number of duty cycle sub-bands for a region.
+pub const MAX_SUB_BANDS: usize = 8;
+
+/// A regulatory sub-band, such as an ETSI sub-band in EU868, subject to a duty cycle limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SubBand {
+    /// Lowest frequency within the sub-band.
+    pub min_frequency: u32,
+    /// Frequency at which the sub-band ends, which is not within it, so adjacent sub-bands share a boundary.
+    pub max_frequency: u32,
+    /// Inverse of the duty cycle, for example 100 for a 1% duty cycle.
+    pub duty_cycle_divisor: u32,
+}
+
+impl SubBand {
+    /// Creation.
+    pub const fn new(min_frequency: u32, max_frequency: u32, duty_cycle_divisor: u32) -> Self {
+        Self { min_frequency, max_frequency, duty_cycle_divisor }
+    }
+
+    fn contains(&self, frequency: u32) -> bool {
+        (self.min_frequency..self.max_frequency).contains(&frequency)
+    }
+}
+
+/// Times, as reported by [`Timer::now`](crate::device::timer::Timer::now), at which transmission is next allowed.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct DutyCycle {
+    sub_band_ready_at: [u64; MAX_SUB_BANDS],
+    aggregated_ready_at: u64,
+}
+
+impl DutyCycle {
+    /// Get the time in milliseconds before transmission on `frequency` is allowed, or None if allowed now.
+    pub(crate) fn time_off(&self, sub_bands: &[SubBand], frequency: u32, now: u64) -> Option<u64> {
+        let sub_band_ready_at = sub_bands
+            .iter()
+            .take(MAX_SUB_BANDS)
+            .position(|sub_band| sub_band.contains(frequency))
+            .map(|index| self.sub_band_ready_at[index])
+            .unwrap_or_default();
+        let ready_at = max(sub_band_ready_at, self.aggregated_ready_at);
+        (ready_at > now).then(|| ready_at - now)
+    }
+
+    /// Start the time-off following a transmission of `time_on_air` milliseconds on `frequency` ending at `tx_end`.
+    /// `max_duty_cycle` is the aggregated duty cycle set by the network server, where 0 or 1 means no limit.
+    pub(crate) fn record_tx(
+        &mut self,
+        sub_bands: &[SubBand],
+        max_duty_cycle: f32,
+        frequency: u32,
+        tx_end: u64,
+        time_on_air: u64,
+    ) {
+        if let Some(index) =
+            sub_bands.iter().take(MAX_SUB_BANDS).position(|sub_band| sub_band.contains(frequency))
+        {
+            let time_off = time_on_air * (sub_bands[index].duty_cycle_divisor as u64 - 1);
+            self.sub_band_ready_at[index] = tx_end + time_off;
+        }
+        if max_duty_cycle > 0.0 && max_duty_cycle < 1.0 {
+            let time_off = (time_on_air as f32 * (1.0 / max_duty_cycle - 1.0)) as u64;
+            self.aggregated_ready_at = tx_end + time_off;
+        }
+    }
+}
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+/// Aggregated time-off imposed on join-requests, following the retransmission back-off of LoRaWAN 1.0.4: a duty
+/// cycle of 1% during the first hour after the first join-request, 0.1% during the following 10 hours and 0.01%
+/// thereafter.  The state is kept relative to the last update so that it can be persisted, and a join procedure
+/// interrupted by a power cycle resumes where it was left.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct JoinBackOff {
+    /// Time in milliseconds elapsed since the first join-request, or None outside a join procedure.
+    pub(crate) elapsed: Option<u64>,
+    /// Time in milliseconds before the next join-request is allowed.
+    pub(crate) time_off: u64,
+    /// Time, as reported by [`Timer::now`](crate::device::timer::Timer::now), at which `elapsed` and `time_off`
+    /// applied, or None until first used after a power cycle.
+    updated_at: Option<u64>,
+}
+
+impl JoinBackOff {
+    /// Restore the state persisted in seconds.
+    pub(crate) fn restore(elapsed: Option<u32>, time_off: u32) -> Self {
+        Self {
+            elapsed: elapsed.map(|elapsed| elapsed as u64 * 1000),
+            time_off: time_off as u64 * 1000,
+            updated_at: None,
+        }
+    }
+
+    /// Get the time in milliseconds before a join-request is allowed, or None if allowed now.
+    pub(crate) fn time_off(&mut self, now: u64) -> Option<u64> {
+        let updated_at = *self.updated_at.get_or_insert(now);
+        let time_off = self.time_off.saturating_sub(now.saturating_sub(updated_at));
+        (time_off > 0).then_some(time_off)
+    }
+
+    /// Start the time-off following a join-request of `time_on_air` milliseconds ending at `tx_end`.
+    pub(crate) fn record_tx(&mut self, tx_end: u64, time_on_air: u64) {
+        let since_update =
+            self.updated_at.map_or(0, |updated_at| tx_end.saturating_sub(updated_at));
+        let elapsed = self.elapsed.map_or(0, |elapsed| elapsed + since_update);
+        let duty_cycle_divisor = match elapsed {
+            elapsed if elapsed < HOUR_MS => 100,
+            elapsed if elapsed < 11 * HOUR_MS => 1000,
+            _ => 10_000,
+        };
+        self.elapsed = Some(elapsed);
+        self.time_off = time_on_air * (duty_cycle_divisor - 1);
+        self.updated_at = Some(tx_end);
+    }
+
+    /// End the join procedure.
+    pub(crate) fn reset(&mut self) {
+        *self = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUB_BANDS: [SubBand; 2] =
+        [SubBand::new(868_000_000, 868_600_000, 100), SubBand::new(869_400_000, 869_650_000, 10)];
+
+    #[test]
+    fn sub_band_time_off() {
+        let mut duty_cycle = DutyCycle::default();
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 868_100_000, 0), None);
+        duty_cycle.record_tx(&SUB_BANDS, 0.0, 868_100_000, 1_000, 50);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 868_500_000, 1_000), Some(4_950));
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 868_500_000, 5_950), None);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 869_525_000, 1_000), None);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 867_100_000, 1_000), None);
+    }
+
+    #[test]
+    fn sub_band_boundaries() {
+        // the sub-band ending at a frequency does not include it
+        const SUB_BANDS: [SubBand; 2] = [
+            SubBand::new(863_000_000, 865_000_000, 1000),
+            SubBand::new(865_000_000, 868_000_000, 100),
+        ];
+        let mut duty_cycle = DutyCycle::default();
+        duty_cycle.record_tx(&SUB_BANDS, 0.0, 864_900_000, 1_000, 10);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 863_000_000, 1_000), Some(9_990));
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 865_000_000, 1_000), None);
+        duty_cycle.record_tx(&SUB_BANDS, 0.0, 865_000_000, 1_000, 10);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 867_900_000, 1_000), Some(990));
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 868_000_000, 1_000), None);
+    }
+
+    #[test]
+    fn aggregated_time_off() {
+        let mut duty_cycle = DutyCycle::default();
+        duty_cycle.record_tx(&SUB_BANDS, 1.0 / 1024.0, 869_525_000, 1_000, 100);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 869_525_000, 1_000), Some(102_300));
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 867_100_000, 1_000), Some(102_300));
+        duty_cycle.record_tx(&[], 1.0, 867_100_000, 200_000, 100);
+        assert_eq!(duty_cycle.time_off(&SUB_BANDS, 867_100_000, 200_000), None);
+    }
+
+    #[test]
+    fn join_back_off() {
+        let mut join_back_off = JoinBackOff::default();
+        assert_eq!(join_back_off.time_off(1_000), None);
+        join_back_off.record_tx(2_000, 400);
+        assert_eq!(join_back_off.time_off(2_000), Some(39_600));
+        assert_eq!(join_back_off.time_off(41_600), None);
+        join_back_off.record_tx(2_000 + HOUR_MS, 400);
+        assert_eq!(join_back_off.time_off(2_000 + HOUR_MS), Some(399_600));
+        join_back_off.record_tx(2_000 + 11 * HOUR_MS, 400);
+        assert_eq!(join_back_off.time_off(2_000 + 11 * HOUR_MS), Some(3_999_600));
+        join_back_off.reset();
+        assert_eq!(join_back_off.time_off(2_000 + 11 * HOUR_MS), None);
+    }
+
+    #[test]
+    fn join_back_off_restored() {
+        // 2 hours into the join procedure, with 100 s of time-off remaining
+        let mut join_back_off = JoinBackOff::restore(Some(2 * 60 * 60), 100);
+        assert_eq!(join_back_off.time_off(5_000), Some(100_000));
+        assert_eq!(join_back_off.time_off(55_000), Some(50_000));
+        assert_eq!(join_back_off.time_off(105_000), None);
+        join_back_off.record_tx(105_000, 400);
+        assert_eq!(join_back_off.elapsed, Some(2 * HOUR_MS + 100_000));
+        assert_eq!(join_back_off.time_off(105_000), Some(399_600));
+    }
+}
@@ -2,23 +2,32 @@
 
 use core::fmt::Debug;
 
+pub mod class_b;
+pub mod duty_cycle;
 pub mod region;
+pub mod time;
 pub mod types;
 use core::{
     cmp::{max, min},
     marker::PhantomData,
 };
 
+use self::class_b::{Beacon, BeaconState, BeaconTiming, ClassB};
+use self::duty_cycle::DutyCycle;
 use self::region::{
-    channel_plan::{Channel, ChannelPlan},
-    Region,
+    any::AnyRegion,
+    channel_plan::{Channel, ChannelPlan, MAX_CHANNELS},
+    ListenBeforeTalk, Region,
 };
+use self::time::TimeSync;
 
 use crate::device::DeviceSpecs;
 use crate::{
-    device::types::{RfConfig, TxConfig},
-    device::{rng::Rng, timer::Timer, Device},
+    device::types::{Datarate, RfConfig, TxConfig},
+    device::{non_volatile_store::NonVolatileStore, rng::Rng, timer::Timer, Device},
 };
+use encoding::keys::{AppSKey, NwkSKey};
+use encoding::parser::DevAddr;
 use encoding::parser::{
     parse, AsPhyPayloadBytes, DecryptedDataPayload, DevNonce, EncryptedJoinAcceptPayload,
     FRMMacCommands,
@@ -27,17 +36,18 @@ use encoding::{
     creator::{DataPayloadCreator, JoinRequestCreator},
     default_crypto::DefaultFactory,
     maccommandcreator::{
-        DevStatusAnsCreator, DlChannelAnsCreator, DutyCycleAnsCreator, LinkADRAnsCreator,
-        NewChannelAnsCreator, RXParamSetupAnsCreator, RXTimingSetupAnsCreator,
-        TXParamSetupAnsCreator, UplinkMacCommandCreator,
+        DevStatusAnsCreator, DeviceTimeReqCreator, DlChannelAnsCreator, DutyCycleAnsCreator,
+        LinkADRAnsCreator, LinkCheckReqCreator, NewChannelAnsCreator, RXParamSetupAnsCreator,
+        RXTimingSetupAnsCreator, TXParamSetupAnsCreator, UplinkMacCommandCreator,
     },
     maccommands::{DLSettings, DownlinkMacCommand, MacCommandIterator},
     parser::{DataHeader, FCtrl, FRMPayload, PhyPayload},
 };
 
 use heapless::Vec;
-use lora_modulation::{BaseBandModulationParams, CodingRate};
-use lora_phy::mod_params::{PacketParams, PacketStatus};
+use lora_modulation::CodingRate;
+use lora_phy::mod_params::{PacketStatus, RadioError};
+use lora_phy::RxMode;
 use types::*;
 
 #[derive(Debug)]
@@ -53,7 +63,17 @@ pub enum Error {
     NetworkNotJoined,
     SessionExpired,
     FOptsFull,
-    NoValidChannelFound,
+    /// No channel was available to send on, for the given reason.
+    NoValidChannelFound(NoValidChannelReason),
+    ClassNotEnabled,
+    BeaconNotLocked,
+    BeaconLost,
+    InvalidPingSlotPeriodicity,
+    PayloadSizeExceeded,
+    /// Another region was selected through [`AnyRegion::select`] after the `Mac` was created.
+    RegionMismatch,
+    /// The `Mac` uses [`AnyRegion`] and no region was selected through [`AnyRegion::select`].
+    RegionNotSelected,
     Encoding(encoding::parser::Error),
     Creator(encoding::creator::Error),
     MacCommandCreator(encoding::maccommandcreator::Error),
@@ -68,6 +88,210 @@ where
     }
 }
 
+/// Reason no channel was available to send on.  Where channels are unavailable for different reasons, the reason
+/// most likely to clear soonest is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NoValidChannelReason {
+    /// The channel mask enables no channel.
+    ChannelMask,
+    /// No enabled channel supports the data rate.
+    DataRate(DR),
+    /// Transmission would exceed a duty cycle limit or the join-request back-off; retry after the given number of
+    /// milliseconds.
+    DutyCycle(u64),
+    /// Listen Before Talk found every channel occupied.
+    ChannelsBusy,
+}
+
+impl NoValidChannelReason {
+    fn priority(&self) -> u8 {
+        match self {
+            Self::ChannelMask => 0,
+            Self::DataRate(_) => 1,
+            Self::DutyCycle(_) => 2,
+            Self::ChannelsBusy => 3,
+        }
+    }
+
+    /// Combine with the reason another channel was unavailable, if any.
+    fn combine(self, other: Option<Self>) -> Self {
+        match (self, other) {
+            (Self::DutyCycle(wait), Some(Self::DutyCycle(other_wait))) => {
+                Self::DutyCycle(min(wait, other_wait))
+            }
+            (_, Some(other)) if other.priority() > self.priority() => other,
+            _ => self,
+        }
+    }
+}
+
+/// Listen Before Talk sensing of a channel before transmitting on it.
+pub(crate) trait ChannelSensing {
+    type Error: From<region::Error>;
+
+    /// Receive on the channel given by `rf_config` for the sensing time, reporting whether the RSSI stayed at or
+    /// below the threshold.
+    async fn is_channel_clear(
+        &mut self,
+        rf_config: &RfConfig,
+        lbt: ListenBeforeTalk,
+    ) -> Result<bool, Self::Error>;
+}
+
+impl<D: Device> ChannelSensing for D {
+    type Error = crate::Error<D>;
+
+    async fn is_channel_clear(
+        &mut self,
+        rf_config: &RfConfig,
+        lbt: ListenBeforeTalk,
+    ) -> Result<bool, Self::Error> {
+        // only LoRa channels are sensed
+        let Datarate::LoRa { spreading_factor, bandwidth } = rf_config.data_rate else {
+            return Ok(true);
+        };
+        let mdltn_params = self
+            .radio()
+            .create_modulation_params(
+                spreading_factor,
+                bandwidth,
+                rf_config.coding_rate,
+                rf_config.frequency,
+            )
+            .map_err(crate::device::Error::Radio)?;
+        let rx_pkt_params = self
+            .radio()
+            .create_rx_packet_params(8, false, 255, true, false, &mdltn_params)
+            .map_err(crate::device::Error::Radio)?;
+        self.radio()
+            .prepare_for_rx(RxMode::Continuous, &mdltn_params, &rx_pkt_params)
+            .await
+            .map_err(crate::device::Error::Radio)?;
+        let start = self.timer().now();
+        loop {
+            let rssi = self.radio().get_rssi().await.map_err(crate::device::Error::Radio)?;
+            if rssi > lbt.rssi_threshold {
+                trace!("channel busy, rssi {}", rssi);
+                return Ok(false);
+            }
+            if self.timer().now() - start >= lbt.sensing_time_ms as u64 {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Beacons, indicated by `beacon_len`, use an implicit header without CRC and are not IQ inverted.
+async fn prepare_for_rx<D: Device>(
+    device: &mut D,
+    rf_config: &RfConfig,
+    rx_mode: RxMode,
+    beacon_len: Option<u8>,
+) -> Result<PreparedRx, crate::Error<D>> {
+    let (spreading_factor, bandwidth) = match rf_config.data_rate {
+        Datarate::LoRa { spreading_factor, bandwidth } => (spreading_factor, bandwidth),
+        Datarate::Fsk { .. } => {
+            let window_ms = match rx_mode {
+                RxMode::Continuous => None,
+                _ => Some(FSK_RX_WINDOW_MS),
+            };
+            return Ok(PreparedRx::Fsk(window_ms));
+        }
+        Datarate::LrFhss { .. } => return Err(crate::Error::Mac(Error::UnsupportedDataRate)),
+    };
+    let mdltn_params = device
+        .radio()
+        .create_modulation_params(
+            spreading_factor,
+            bandwidth,
+            rf_config.coding_rate,
+            rf_config.frequency,
+        )
+        .map_err(crate::device::Error::Radio)?;
+
+    let rx_pkt_params = match beacon_len {
+        Some(len) => device.radio().create_rx_packet_params(
+            class_b::BEACON_PREAMBLE_SYMBOLS,
+            true,
+            len,
+            false,
+            false,
+            &mdltn_params,
+        ),
+        None => device.radio().create_rx_packet_params(8, false, 255, true, true, &mdltn_params),
+    }
+    .map_err(crate::device::Error::Radio)?;
+
+    device
+        .radio()
+        .prepare_for_rx(rx_mode, &mdltn_params, &rx_pkt_params)
+        .await
+        .map_err(crate::device::Error::Radio)?;
+    Ok(PreparedRx::LoRa(rx_pkt_params))
+}
+
+/// Receive a frame in a window prepared by [`prepare_for_rx`].
+async fn rx<D: Device>(
+    device: &mut D,
+    rf_config: &RfConfig,
+    prepared_rx: &PreparedRx,
+    buf: &mut [u8],
+) -> Result<(u8, PacketStatus), RadioError> {
+    match prepared_rx {
+        PreparedRx::LoRa(packet_params) => device.radio().rx(packet_params, buf).await,
+        PreparedRx::Fsk(window_ms) => device.fsk_rx(rf_config, *window_ms, buf).await,
+    }
+}
+
+/// Reception in the RX1 and RX2 windows following an uplink.
+pub(crate) trait WindowReception {
+    type Error: From<region::Error>;
+
+    /// Receive on the channel given by `rf_config` in the window opening `open_ms` after the end of the uplink,
+    /// returning `None` if the window closes without a frame.
+    async fn receive_in_window(
+        &mut self,
+        window: &Window,
+        rf_config: &RfConfig,
+        rx_mode: RxMode,
+        open_ms: u64,
+        buf: &mut [u8],
+    ) -> Result<Option<(u8, PacketStatus)>, Self::Error>;
+}
+
+impl<D: Device> WindowReception for D {
+    type Error = crate::Error<D>;
+
+    async fn receive_in_window(
+        &mut self,
+        window: &Window,
+        rf_config: &RfConfig,
+        rx_mode: RxMode,
+        open_ms: u64,
+        buf: &mut [u8],
+    ) -> Result<Option<(u8, PacketStatus)>, Self::Error> {
+        let prepared_rx = match window {
+            Window::_1 => {
+                self.timer().at(open_ms).await.map_err(crate::device::Error::Timer)?;
+                prepare_for_rx(self, rf_config, rx_mode, None).await?
+            }
+            Window::_2 => {
+                let prepared_rx = prepare_for_rx(self, rf_config, rx_mode, None).await?;
+                self.timer().at(open_ms).await.map_err(crate::device::Error::Timer)?;
+                prepared_rx
+            }
+        };
+        match rx(self, rf_config, &prepared_rx, buf).await {
+            Ok(ret) => Ok(Some(ret)),
+            // Bail on error other than timeout ???
+            Err(_e) if *window == Window::_1 => Ok(None),
+            Err(RadioError::ReceiveTimeout) => Ok(None),
+            Err(e) => Err(crate::Error::Device(crate::device::Error::Radio(e))),
+        }
+    }
+}
+
 /// Composition of properties needed to guide LoRaWAN MAC layer processing, supporting the LoRaWAN MAC API.
 #[repr(C)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -81,6 +305,11 @@ where
     pub(crate) region: PhantomData<R>,
     pub(crate) uplink_cmds: Vec<UplinkMacCommandCreator, 15>,
     pub(crate) ack_next: bool,
+    pub(crate) class: Class,
+    pub(crate) class_b: ClassB,
+    pub(crate) last_tx_end: Option<u64>,
+    pub(crate) time_sync: TimeSync,
+    pub(crate) duty_cycle: DutyCycle,
     pub(crate) configuration: Configuration,
     pub(crate) credentials: Credentials,
 }
@@ -92,12 +321,21 @@ where
 {
     /// Creation.
     pub fn new(configuration: Configuration, credentials: Credentials) -> Self {
+        let channel_plan = C::default();
+        // only a Mac for a region selected at runtime persists the region
+        let configuration =
+            Configuration { region: channel_plan.selected_region(), ..configuration };
         Self {
             session: None,
-            channel_plan: Default::default(),
+            channel_plan,
             region: PhantomData,
             uplink_cmds: Vec::new(),
             ack_next: false,
+            class: Class::A,
+            class_b: Default::default(),
+            last_tx_end: None,
+            time_sync: Default::default(),
+            duty_cycle: Default::default(),
             configuration,
             credentials,
         }
@@ -127,9 +365,9 @@ where
     fn max_data_rate<D: DeviceSpecs>() -> DR {
         match D::max_data_rate() {
             Some(device_max_data_rate) => {
-                min(device_max_data_rate as u8, R::ul_data_rate_range().1 as u8).try_into().unwrap()
+                min(device_max_data_rate as u8, R::max_ul_data_rate() as u8).try_into().unwrap()
             }
-            None => R::ul_data_rate_range().1,
+            None => R::max_ul_data_rate(),
         }
     }
 
@@ -153,33 +391,58 @@ where
     fn validate_data_rate<D: DeviceSpecs>(dr: u8) -> bool {
         if let Ok(dr) = DR::try_from(dr) {
             dr.in_range((Self::min_data_rate::<D>(), Self::max_data_rate::<D>()))
+                && Self::is_modulation_supported::<D>(dr)
         } else {
             false
         }
     }
 
+    /// Can the radio of the given end device use the modulation of the data rate?
+    fn is_modulation_supported<D: DeviceSpecs>(dr: DR) -> bool {
+        match R::convert_data_rate(dr) {
+            Ok(Datarate::Fsk { .. }) => D::supports_fsk(),
+            Ok(Datarate::LrFhss { .. }) => D::supports_lr_fhss(),
+            _ => true,
+        }
+    }
+
     /// Are the downlink data rate settings in range for the given end device?
     fn validate_dl_settings<D: DeviceSpecs>(dl_settings: DLSettings) -> (bool, bool) {
         let rx1_data_rate_offset_ack =
             Self::validate_rx1_data_rate_offset::<D>(dl_settings.rx1_dr_offset());
-        let rx2_data_rate_ack = Self::validate_data_rate::<D>(dl_settings.rx2_data_rate());
+        // LR-FHSS is only used for uplinks
+        let rx2_data_rate_ack = Self::validate_data_rate::<D>(dl_settings.rx2_data_rate())
+            && !matches!(
+                DR::try_from(dl_settings.rx2_data_rate()).map(R::convert_data_rate),
+                Ok(Ok(Datarate::LrFhss { .. }))
+            );
         (rx1_data_rate_offset_ack, rx2_data_rate_ack)
     }
 
+    /// Get the maximum EIRP allowed by the network server, which a TXParamSetupReq may have changed from the
+    /// regional default.
+    fn network_max_eirp(configuration: &Configuration) -> i8 {
+        configuration.max_eirp.unwrap_or(R::max_eirp())
+    }
+
     /// Get the maximum EIRP for the end device.
-    fn max_eirp<D: DeviceSpecs>() -> i8 {
+    fn max_eirp<D: DeviceSpecs>(configuration: &Configuration) -> i8 {
+        let max_eirp = Self::network_max_eirp(configuration);
         if let Some(device_max_eirp) = D::max_eirp() {
-            min(R::max_eirp(), device_max_eirp)
+            min(max_eirp, device_max_eirp)
         } else {
-            R::max_eirp()
+            max_eirp
         }
     }
 
     /// Get the transmission power based on the frame type.
     fn get_tx_pwr<D: DeviceSpecs>(frame: Frame, configuration: &Configuration) -> i8 {
+        let max_eirp = Self::max_eirp::<D>(configuration);
         match frame {
-            Frame::Join => Self::max_eirp::<D>(),
-            Frame::Data => configuration.tx_power.unwrap_or(Self::max_eirp::<D>()),
+            Frame::Join => max_eirp,
+            Frame::Data => {
+                configuration.tx_power.map_or(max_eirp, |tx_power| min(tx_power, max_eirp))
+            }
         }
     }
 
@@ -192,6 +455,98 @@ where
         }
     }
 
+    /// Resume a session restored through [`Device::hydrate_from_non_volatile`], avoiding a new join.
+    pub fn restore_session(&mut self, session: Session) {
+        if let Some(variant) = session.channel_plan_variant {
+            if self.channel_plan.set_variant(variant).is_err() {
+                warn!("channel plan variant {} not restored", variant);
+            }
+        }
+        if let Some(mask) = session.channel_mask {
+            if self.channel_plan.restore_channel_mask(mask).is_err() {
+                warn!("channel mask not restored");
+            }
+        }
+        self.session.replace(session);
+    }
+
+    /// Keep the channel plan variant and channel mask with the session, so they are persisted.
+    fn update_session_channel_plan(&mut self) {
+        if let Some(session) = &mut self.session {
+            session.channel_plan_variant = self.channel_plan.variant();
+            session.channel_mask = self.channel_plan.persisted_channel_mask();
+        }
+    }
+
+    /// Get the class of operation of the end device.
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    /// Set the class of operation of the end device.  Class C is selected out-of-band
+    /// for LoRaWAN 1.0.4; the network server must be configured to match.  Class B is
+    /// entered through [`Mac::acquire_beacon`], so selecting it here only takes effect once a beacon is locked.
+    pub fn set_class(&mut self, class: Class) {
+        self.class = class;
+        if class != Class::B {
+            self.class_b.beacon = None;
+        }
+    }
+
+    /// Request a Class B ping slot periodicity, where the end device listens every 2^`periodicity` seconds.
+    /// The request is sent in the FOpts of the next uplink and applied once the network server answers.
+    pub fn set_ping_slot_periodicity(&mut self, periodicity: u8) -> Result<(), crate::mac::Error> {
+        if periodicity > 7 {
+            return Err(crate::mac::Error::InvalidPingSlotPeriodicity);
+        }
+        self.class_b.pending_periodicity = Some(periodicity);
+        self.push_class_b_cmd(class_b::UplinkCommand::PingSlotInfoReq { periodicity })
+    }
+
+    /// Ask the network server for the time of the next beacon in the FOpts of the next uplink, which shortens
+    /// a subsequent [`Mac::acquire_beacon`].
+    pub fn request_beacon_timing(&mut self) -> Result<(), crate::mac::Error> {
+        self.push_class_b_cmd(class_b::UplinkCommand::BeaconTimingReq)
+    }
+
+    /// Ask the network server for the link margin and the number of gateways which received the next uplink,
+    /// reported through [`Device::handle_link_check`].  The request is sent in the FOpts of the next uplink, or
+    /// immediately through [`Mac::send_mac_commands`].
+    pub fn request_link_check(&mut self) -> Result<(), crate::mac::Error> {
+        self.push_uplink_cmd(UplinkMacCommandCreator::LinkCheckReq(LinkCheckReqCreator::new()))
+    }
+
+    /// Ask the network server for the current GPS time, reported through [`Device::handle_device_time`] and
+    /// kept thereafter by [`Mac::now_gps`].  The request is sent in the FOpts of the next uplink, or immediately
+    /// through [`Mac::send_mac_commands`].  Repeating the request refines the drift estimate of the local clock.
+    pub fn request_device_time(&mut self) -> Result<(), crate::mac::Error> {
+        self.push_uplink_cmd(UplinkMacCommandCreator::DeviceTimeReq(DeviceTimeReqCreator::new()))
+    }
+
+    /// Get the current GPS time in milliseconds, as synchronised by the last DeviceTimeAns and corrected for the
+    /// drift of the local clock, or None if no DeviceTimeAns has been received.
+    pub fn now_gps<D: Device>(&self, device: &mut D) -> Option<u64> {
+        self.time_sync.now_gps(device.timer().now())
+    }
+
+    /// Get the synchronisation of the local clock to GPS time.
+    pub fn time_sync(&self) -> &TimeSync {
+        &self.time_sync
+    }
+
+    fn push_uplink_cmd(&mut self, cmd: UplinkMacCommandCreator) -> Result<(), crate::mac::Error> {
+        self.uplink_cmds
+            .retain(|pending| core::mem::discriminant(pending) != core::mem::discriminant(&cmd));
+        self.uplink_cmds.push(cmd).map_err(|_| crate::mac::Error::FOptsFull)
+    }
+
+    fn push_class_b_cmd(&mut self, cmd: class_b::UplinkCommand) -> Result<(), crate::mac::Error> {
+        self.class_b
+            .uplink_cmds
+            .retain(|pending| core::mem::discriminant(pending) != core::mem::discriminant(&cmd));
+        self.class_b.uplink_cmds.push(cmd).map_err(|_| crate::mac::Error::FOptsFull)
+    }
+
     fn handle_dl_settings(&mut self, dl_settings: DLSettings) -> Result<(), crate::mac::Error> {
         self.configuration.rx1_data_rate_offset = Some(dl_settings.rx1_dr_offset());
         let rx2_data_rate: DR = dl_settings
@@ -212,7 +567,21 @@ where
 
     fn rx1_data_rate(&self, tx_dr: DR) -> DR {
         let offset = self.rx1_data_rate_offset();
-        R::get_rx1_dr(tx_dr, offset).unwrap_or(R::default_data_rate())
+        let dr = R::get_rx1_dr(tx_dr, offset).unwrap_or(R::default_data_rate());
+        let min_dr = R::min_rx1_data_rate(self.downlink_dwell_time());
+        if (dr as u8) < (min_dr as u8) {
+            min_dr
+        } else {
+            dr
+        }
+    }
+
+    fn uplink_dwell_time(&self) -> bool {
+        self.configuration.uplink_dwell_time.unwrap_or(R::default_dwell_time(true))
+    }
+
+    fn downlink_dwell_time(&self) -> bool {
+        self.configuration.downlink_dwell_time.unwrap_or(R::default_dwell_time(false))
     }
 
     fn adr_ack_limit<D: DeviceSpecs>() -> u8 {
@@ -223,25 +592,30 @@ where
         D::adr_ack_delay().unwrap_or(R::default_adr_ack_delay())
     }
 
-    fn adr_back_off<D: DeviceSpecs>(&mut self) {
-        if let Some(session) = &self.session {
-            if session.adr_ack_cnt >= (Self::adr_ack_limit::<D>() + Self::adr_ack_delay::<D>())
+    /// Is a step of the ADR back-off due for the next uplink?
+    fn is_adr_back_off_due<D: DeviceSpecs>(&self) -> bool {
+        self.session.as_ref().is_some_and(|session| {
+            session.adr_ack_cnt >= (Self::adr_ack_limit::<D>() + Self::adr_ack_delay::<D>())
                 && (session.adr_ack_cnt - Self::adr_ack_limit::<D>()) % Self::adr_ack_delay::<D>()
                     == 0
-            {
-                // try to regain connectivity
-                if self.configuration.tx_power.is_some() {
-                    // First reset tx_power to default
-                    self.configuration.tx_power = None;
-                }
-                // Next increse tx data rate until it reaches default
-                if self.configuration.tx_data_rate.is_some() {
-                    self.configuration.tx_data_rate =
-                        R::next_adr_data_rate(self.configuration.tx_data_rate);
-                } else {
-                    self.configuration.number_of_transmissions = 1;
-                    self.channel_plan.reactivate_channels();
-                }
+        })
+    }
+
+    fn adr_back_off<D: DeviceSpecs>(&mut self) {
+        if self.is_adr_back_off_due::<D>() {
+            // try to regain connectivity
+            if self.configuration.tx_power.is_some() {
+                // First reset tx_power to default
+                self.configuration.tx_power = None;
+            }
+            // Next increse tx data rate until it reaches default
+            if self.configuration.tx_data_rate.is_some() {
+                self.configuration.tx_data_rate =
+                    R::next_adr_data_rate(self.configuration.tx_data_rate);
+            } else {
+                self.configuration.number_of_transmissions = 1;
+                self.channel_plan.reactivate_channels();
+                self.update_session_channel_plan();
             }
         }
     }
@@ -300,59 +674,86 @@ where
         Ok(tx_config)
     }
 
-    fn create_rf_config<D: Device>(
+    fn create_rf_config(
         &self,
         window: &Window,
         data_rate: DR,
         channel: &C::Channel,
-    ) -> Result<RfConfig, crate::Error<D>> {
-        let data_rate = match window {
-            Window::_1 => self.rx1_data_rate(data_rate),
-            Window::_2 => self.configuration.rx2_data_rate.unwrap_or(R::default_rx2_data_rate()),
-        };
+    ) -> Result<RfConfig, region::Error> {
+        match window {
+            Window::_1 => {
+                let data_rate = R::convert_data_rate(self.rx1_data_rate(data_rate))?;
+                let frequency = channel.get_dl_frequency();
+                Ok(RfConfig { frequency, coding_rate: CodingRate::_4_5, data_rate })
+            }
+            Window::_2 => self.create_rx2_rf_config(Some(channel)),
+        }
+    }
+
+    /// The RX2 settings are also used for Class C continuous reception, where there is no uplink channel.
+    fn create_rx2_rf_config(
+        &self,
+        channel: Option<&C::Channel>,
+    ) -> Result<RfConfig, region::Error> {
+        let data_rate = self.configuration.rx2_data_rate.unwrap_or(R::default_rx2_data_rate());
         let data_rate = R::convert_data_rate(data_rate)?;
-        let frequency = match window {
-            Window::_1 => channel.get_dl_frequency(),
-            Window::_2 => self.configuration.rx2_frequency.unwrap_or_else(R::default_rx2_frequency),
-        };
+        let frequency = self
+            .configuration
+            .rx2_frequency
+            .or_else(|| self.channel_plan.default_rx2_frequency(channel))
+            .unwrap_or_else(R::default_rx2_frequency);
+        Ok(RfConfig { frequency, coding_rate: CodingRate::_4_5, data_rate })
+    }
 
+    /// Get the RF configuration of the beacon sent on the given beacon channel.
+    fn create_beacon_rf_config<D: Device>(&self, channel: u8) -> Result<RfConfig, crate::Error<D>> {
+        let data_rate = R::convert_data_rate(R::beacon_data_rate())?;
+        let frequency = self
+            .class_b
+            .beacon_frequency
+            .or_else(|| self.channel_plan.beacon_frequency(channel))
+            .unwrap_or_else(|| R::beacon_frequency(channel));
         Ok(RfConfig { frequency, coding_rate: CodingRate::_4_5, data_rate })
     }
 
-    async fn prepare_for_rx<D: Device>(
+    /// Get the RF configuration of the ping slots in the beacon period starting at `beacon_time`.
+    fn create_ping_slot_rf_config<D: Device>(
         &self,
-        rf_config: &RfConfig,
-        device: &mut D,
-    ) -> Result<PacketParams, crate::Error<D>> {
-        let mdltn_params = device
-            .radio()
-            .create_modulation_params(
-                rf_config.data_rate.spreading_factor,
-                rf_config.data_rate.bandwidth,
-                rf_config.coding_rate,
-                rf_config.frequency,
-            )
-            .map_err(crate::device::Error::Radio)?;
+        beacon_time: u32,
+        dev_addr: [u8; 4],
+    ) -> Result<RfConfig, crate::Error<D>> {
+        let data_rate = self.class_b.ping_slot_data_rate.unwrap_or(R::beacon_data_rate());
+        let data_rate = R::convert_data_rate(data_rate)?;
+        let frequency = self.class_b.ping_slot_frequency.unwrap_or_else(|| {
+            let dev_addr = u32::from_le_bytes(dev_addr);
+            let channel = class_b::channel_index(beacon_time, dev_addr, R::beacon_channels());
+            self.channel_plan
+                .beacon_frequency(channel)
+                .unwrap_or_else(|| R::beacon_frequency(channel))
+        });
+        Ok(RfConfig { frequency, coding_rate: CodingRate::_4_5, data_rate })
+    }
 
-        let rx_pkt_params = device
-            .radio()
-            .create_rx_packet_params(8, false, 255, true, true, &mdltn_params)
-            .map_err(crate::device::Error::Radio)?;
-        let bb = BaseBandModulationParams::new(
-            rf_config.data_rate.spreading_factor,
-            rf_config.data_rate.bandwidth,
-            rf_config.coding_rate,
-        );
+    /// Get the receive mode for an RX1 or RX2 window, which closes if no preamble is detected shortly after opening.
+    fn rx_window_mode(rf_config: &RfConfig) -> RxMode {
         const PREAMBLE_SYMBOLS: u16 = 13; // 12.25
-        let num_symbols = PREAMBLE_SYMBOLS + bb.delay_in_symbols(100);
-        let rx_mode = lora_phy::RxMode::Single(num_symbols);
 
-        device
-            .radio()
-            .prepare_for_rx(rx_mode, &mdltn_params, &rx_pkt_params)
-            .await
-            .map_err(crate::device::Error::Radio)?;
-        Ok(rx_pkt_params)
+        // FSK windows are given a duration by prepare_for_rx instead
+        let num_symbols = rf_config
+            .lora_modulation_params()
+            .map_or(0, |bb| PREAMBLE_SYMBOLS + bb.delay_in_symbols(100));
+        RxMode::Single(num_symbols)
+    }
+
+    /// Does the channel mask enable at least one defined uplink channel supporting the data rate?
+    fn is_channel_mask_usable(&self, channel_mask: &[bool; MAX_CHANNELS], data_rate: DR) -> bool {
+        (0..MAX_CHANNELS).any(|index| {
+            channel_mask[index]
+                && self
+                    .channel_plan
+                    .get_channel(index)
+                    .is_some_and(|channel| data_rate.in_range(channel.get_ul_data_rate_range()))
+        })
     }
 
     fn handle_downlink_macs<D: Device>(
@@ -362,6 +763,9 @@ where
         cmds: MacCommandIterator<'_, DownlinkMacCommand<'_>>,
     ) -> Result<(), crate::Error<D>> {
         let mut channel_mask = self.channel_plan.get_channel_mask();
+        // index of the first answer to the current block of LinkADRReq commands and whether the channel mask
+        // commands of the block were accepted so far
+        let mut link_adr_block: Option<(usize, bool)> = None;
         let mut cmd_iter = cmds.into_iter().peekable();
         while let Some(cmd) = cmd_iter.next() {
             trace!("hadling command {:?}", cmd);
@@ -371,7 +775,14 @@ where
                     None
                 }
                 DownlinkMacCommand::DeviceTimeAns(payload) => {
-                    device.handle_device_time(payload.seconds(), payload.nano_seconds());
+                    let now = device.timer().now();
+                    self.time_sync.synchronise(
+                        self.last_tx_end.unwrap_or(now),
+                        time::device_time_ms(payload.seconds(), payload.nano_seconds()),
+                    );
+                    if let Some(gps_time_ms) = self.time_sync.now_gps(now) {
+                        device.handle_device_time(gps_time_ms);
+                    }
                     None
                 }
                 DownlinkMacCommand::LinkADRReq(payload) => {
@@ -379,7 +790,7 @@ where
                     let tx_power_res = R::modify_dbm(
                         payload.tx_power(),
                         self.configuration.tx_power,
-                        R::max_eirp(),
+                        Self::network_max_eirp(&self.configuration),
                     );
                     let data_rate_res: Result<Option<DR>, ()> = if payload.data_rate() == 0xF {
                         Ok(self.configuration.tx_data_rate)
@@ -393,6 +804,11 @@ where
                         payload.redundancy().channel_mask_control(),
                     );
 
+                    let (block_start, channel_mask_ok) =
+                        link_adr_block.get_or_insert((self.uplink_cmds.len(), true));
+                    *channel_mask_ok &= channel_mask_res.is_ok();
+                    let block_start = *block_start;
+
                     ans.set_tx_power_ack(tx_power_res.is_ok());
                     ans.set_data_rate_ack(data_rate_res.is_ok());
                     ans.set_channel_mask_ack(channel_mask_res.is_ok());
@@ -400,6 +816,26 @@ where
                     match cmd_iter.peek() {
                         Some(DownlinkMacCommand::LinkADRReq(_)) => (),
                         _ => {
+                            let (_, channel_mask_ok) = link_adr_block.take().unwrap_or_default();
+                            // the whole block is rejected if the channel mask would leave no channel usable at
+                            // the data rate in effect after the block
+                            let data_rate = match data_rate_res {
+                                Ok(Some(data_rate)) => data_rate,
+                                _ => self.tx_data_rate(),
+                            };
+                            let channel_mask_res = if channel_mask_ok
+                                && self.is_channel_mask_usable(&channel_mask, data_rate)
+                            {
+                                Ok(())
+                            } else {
+                                for answer in self.uplink_cmds[block_start..].iter_mut() {
+                                    if let UplinkMacCommandCreator::LinkADRAns(answer) = answer {
+                                        answer.set_channel_mask_ack(false);
+                                    }
+                                }
+                                ans.set_channel_mask_ack(false);
+                                Err(region::Error::NoValidChannelFound)
+                            };
                             // The end-device SHALL respond to all LinkADRReq commands
                             // with a LinkADRAns indicating which command elements were accepted and which were
                             // rejected. This behavior differs from when the uplink ADR bit is set, in which case the end-
@@ -416,6 +852,8 @@ where
                                     self.configuration.tx_data_rate = new_data_rate
                                 }
                                 if channel_mask_res.is_ok() {
+                                    self.channel_plan.set_channel_mask(channel_mask)?;
+                                    self.update_session_channel_plan();
                                     self.configuration.number_of_transmissions =
                                         payload.redundancy().number_of_transmissions();
                                     if self.configuration.number_of_transmissions == 0 {
@@ -514,12 +952,15 @@ where
                     self.configuration.rx_delay = Some(delay);
                     Some(UplinkMacCommandCreator::RXTimingSetupAns(RXTimingSetupAnsCreator::new()))
                 }
-                DownlinkMacCommand::TXParamSetupReq(_) => {
+                DownlinkMacCommand::TXParamSetupReq(payload) => {
                     if R::supports_tx_param_setup() {
-                        let ans = TXParamSetupAnsCreator::new();
-                        let _ret = Some(UplinkMacCommandCreator::TXParamSetupAns(ans));
-                        //persist_configuration = true;
-                        todo!("TXParamSetupReq not implemented yet");
+                        self.configuration.uplink_dwell_time = Some(payload.uplink_dwell_time());
+                        self.configuration.downlink_dwell_time =
+                            Some(payload.downlink_dwell_time());
+                        self.configuration.max_eirp = Some(payload.max_eirp() as i8);
+                        Some(
+                            UplinkMacCommandCreator::TXParamSetupAns(TXParamSetupAnsCreator::new()),
+                        )
                     } else {
                         None
                     }
@@ -533,46 +974,141 @@ where
         Ok(())
     }
 
-    async fn rx_with_timeout<D: Device>(
+    /// Process a sequence of downlink MAC commands, handling the Class B commands separately.
+    fn handle_downlink_mac_bytes<D: Device>(
+        &mut self,
+        device: &mut D,
+        packet_status: PacketStatus,
+        data: &[u8],
+    ) -> Result<(), crate::Error<D>> {
+        let mut cmds = [0u8; 255];
+        let (len, class_b_cmds) = class_b::split_commands(data, &mut cmds);
+        self.handle_downlink_macs(device, packet_status, MacCommandIterator::new(&cmds[..len]))?;
+        for cmd in class_b_cmds {
+            self.handle_class_b_mac::<D>(cmd)?;
+        }
+        Ok(())
+    }
+
+    fn handle_class_b_mac<D: Device>(
+        &mut self,
+        cmd: class_b::DownlinkCommand,
+    ) -> Result<(), crate::Error<D>> {
+        trace!("hadling command {:?}", cmd);
+        let res = match cmd {
+            class_b::DownlinkCommand::PingSlotInfoAns => {
+                if let Some(periodicity) = self.class_b.pending_periodicity.take() {
+                    self.class_b.periodicity = Some(periodicity);
+                }
+                None
+            }
+            class_b::DownlinkCommand::PingSlotChannelReq { frequency, data_rate } => {
+                let frequency_ack = frequency == 0 || Self::validate_frequency::<D>(frequency);
+                let data_rate = DR::try_from(data_rate)
+                    .ok()
+                    .filter(|data_rate| R::convert_data_rate(*data_rate).is_ok());
+                if frequency_ack && data_rate.is_some() {
+                    self.class_b.ping_slot_frequency = Some(frequency).filter(|f| *f != 0);
+                    self.class_b.ping_slot_data_rate = data_rate;
+                }
+                Some(class_b::UplinkCommand::PingSlotChannelAns {
+                    frequency_ack,
+                    data_rate_ack: data_rate.is_some(),
+                })
+            }
+            class_b::DownlinkCommand::BeaconTimingAns { delay, channel } => {
+                // the next beacon starts between 30 ms x Delay and 30 ms x (Delay + 1) after the end of the uplink
+                if let Some(last_tx_end) = self.last_tx_end {
+                    let at = last_tx_end
+                        + class_b::PING_SLOT_LEN_MS * delay as u64
+                        + class_b::PING_SLOT_LEN_MS / 2;
+                    self.class_b.beacon_timing = Some(BeaconTiming { at, channel });
+                }
+                None
+            }
+            class_b::DownlinkCommand::BeaconFreqReq { frequency } => {
+                let frequency_ack = frequency == 0 || Self::validate_frequency::<D>(frequency);
+                if frequency_ack {
+                    self.class_b.beacon_frequency = Some(frequency).filter(|f| *f != 0);
+                }
+                Some(class_b::UplinkCommand::BeaconFreqAns { frequency_ack })
+            }
+        };
+        if let Some(uplink_cmd) = res {
+            trace!("answer {:?}", uplink_cmd);
+            self.push_class_b_cmd(uplink_cmd)?;
+        }
+        Ok(())
+    }
+
+    /// Receive in RX1 and then RX2, which close at the Class A times in every class.  Class C continuous reception
+    /// is done by [`Mac::receive`].
+    async fn rx_with_timeout<W: WindowReception>(
         &self,
         frame: Frame,
-        device: &mut D,
+        receiver: &mut W,
         buf: &mut [u8],
         data_rate: DR,
         channel: &C::Channel,
-    ) -> Result<Option<(u8, PacketStatus)>, crate::Error<D>> {
+    ) -> Result<Option<(u8, PacketStatus)>, W::Error> {
         let windows = self.get_rx_windows(frame);
-
-        let rf_config = self.create_rf_config(&Window::_1, data_rate, channel)?;
-        debug!("rf config RX1 {:?}", rf_config);
-        device
-            .timer()
-            .at(windows.get_open(&Window::_1) as u64)
-            .await
-            .map_err(|e| crate::Error::Device(crate::device::Error::Timer(e)))?;
-        let packet_params = self.prepare_for_rx(&rf_config, device).await?;
-
-        match device.radio().rx(&packet_params, buf).await {
-            Ok(ret) => {
+        for window in [Window::_1, Window::_2] {
+            let rf_config = self.create_rf_config(&window, data_rate, channel)?;
+            debug!("rf config {:?}", rf_config);
+            let rx_mode = Self::rx_window_mode(&rf_config);
+            let open_ms = windows.get_open(&window) as u64;
+            if let Some(ret) =
+                receiver.receive_in_window(&window, &rf_config, rx_mode, open_ms, buf).await?
+            {
                 return Ok(Some(ret));
             }
-            // Bail on error other than timeout ???
-            Err(_e) => {}
         }
+        Ok(None)
+    }
 
-        let rf_config = self.create_rf_config(&Window::_2, data_rate, channel)?;
-        debug!("rf config RX2 {:?}", rf_config);
-        let packet_params = self.prepare_for_rx(&rf_config, device).await?;
-        device
-            .timer()
-            .at(windows.get_open(&Window::_2) as u64)
-            .await
-            .map_err(|e| crate::Error::Device(crate::device::Error::Timer(e)))?;
-
-        match device.radio().rx(&packet_params, buf).await {
-            Ok(ret) => Ok(Some(ret)),
-            Err(e) => Err(crate::Error::Device(crate::device::Error::Radio(e))),
+    /// Get the length of the MAC commands which will be sent in the FOpts of the next uplink.
+    fn pending_fopts_len(&self) -> usize {
+        let mut pos = 0usize;
+        for cmd in self.uplink_cmds.iter() {
+            if pos + cmd.len() <= 15 {
+                pos += cmd.len();
+            } else {
+                break;
+            }
         }
+        for cmd in self.class_b.uplink_cmds.iter() {
+            if pos + cmd.len() <= 15 {
+                pos += cmd.len();
+            } else {
+                break;
+            }
+        }
+        pos
+    }
+
+    /// Get the time-on-air in microseconds of an uplink carrying `len` bytes of application data at the current
+    /// data rate, including the frame header, the MAC commands pending for the FOpts and the MIC.
+    pub fn uplink_time_on_air_us(&self, len: usize) -> Result<u32, region::Error> {
+        // MHDR, DevAddr, FCtrl, FCnt and MIC, with FPort only present for a non-empty FRMPayload
+        const OVERHEAD: usize = 1 + 4 + 1 + 2 + 4;
+        let fport_len = if len > 0 {
+            1
+        } else {
+            0
+        };
+        let phy_len = OVERHEAD + self.pending_fopts_len() + fport_len + len;
+        R::time_on_air_us(self.tx_data_rate(), min(phy_len, u8::MAX as usize) as u8)
+    }
+
+    /// Get the number of application data bytes which may be sent in the next uplink at the current data rate,
+    /// after the MAC commands pending for the FOpts are accounted for.
+    pub fn max_payload_size(&self) -> Result<usize, region::Error> {
+        self.max_payload_size_at(self.tx_data_rate())
+    }
+
+    fn max_payload_size_at(&self, data_rate: DR) -> Result<usize, region::Error> {
+        let max_frm_payload_size = R::max_frm_payload_size(data_rate, self.uplink_dwell_time())?;
+        Ok(max_frm_payload_size.saturating_sub(self.pending_fopts_len()))
     }
 
     fn prepare_buffer<D: DeviceSpecs>(
@@ -591,7 +1127,11 @@ where
             }
             let mut phy = DataPayloadCreator::new(buf).map_err(crate::mac::Error::Creator)?;
 
+            // the uplink FPending bit signals Class B operation
             let mut fctrl = FCtrl(0x0, true);
+            if self.class == Class::B && self.class_b.beacon.is_some() {
+                fctrl = FCtrl(0x10, true);
+            }
             if adr {
                 fctrl.set_adr();
             }
@@ -624,14 +1164,21 @@ where
                     break;
                 }
             }
+            for cmd in self.class_b.uplink_cmds.iter() {
+                if pos + cmd.len() <= 15 {
+                    pos += cmd.build(&mut dyn_cmds[pos..]);
+                } else {
+                    break;
+                }
+            }
+            // without application data, MAC commands sent on FPort 0 are carried in the FRMPayload
+            let (data, fopts) = if fport == 0 && data.is_empty() {
+                (&dyn_cmds[..pos], &[][..])
+            } else {
+                (data, &dyn_cmds[..pos])
+            };
             let packet = phy
-                .build(
-                    data,
-                    &dyn_cmds[..pos],
-                    session.nwkskey(),
-                    session.appskey(),
-                    &DefaultFactory,
-                )
+                .build(data, fopts, session.nwkskey(), session.appskey(), &DefaultFactory)
                 .map_err(crate::mac::Error::Creator)?;
             trace!("TX: {=[u8]:#02X}", packet);
             Ok(packet.len())
@@ -640,109 +1187,239 @@ where
         }
     }
 
+    /// Get the data rate to send a frame on the channel at `now`, or the reason the channel is unavailable.
+    fn channel_data_rate(
+        &mut self,
+        channel: &C::Channel,
+        frame: Frame,
+        now: u64,
+    ) -> Result<DR, NoValidChannelReason> {
+        let join_wait = match frame {
+            Frame::Join => self.configuration.join_back_off.time_off(now),
+            Frame::Data => None,
+        };
+        if let Some(wait) = max(
+            join_wait,
+            self.duty_cycle.time_off(R::sub_bands(), channel.get_ul_frequency(), now),
+        ) {
+            return Err(NoValidChannelReason::DutyCycle(wait));
+        }
+        let data_rate = R::override_ul_data_rate_if_necessary(
+            self.tx_data_rate(),
+            frame,
+            channel.get_ul_data_rate_range(),
+        );
+        if data_rate.in_range(channel.get_ul_data_rate_range()) {
+            Ok(data_rate)
+        } else {
+            Err(NoValidChannelReason::DataRate(data_rate))
+        }
+    }
+
+    /// Select the given channel to send the frame on, or another enabled channel in its channel block where the given
+    /// channel is unavailable or, for regions requiring Listen Before Talk, busy, with the data rate to use.  The
+    /// reasons channels were unavailable are combined into `unavailable`.
+    async fn select_channel<S: ChannelSensing>(
+        &mut self,
+        sensing: &mut S,
+        channel: C::Channel,
+        frame: Frame,
+        now: u64,
+        random: u32,
+        unavailable: &mut Option<NoValidChannelReason>,
+    ) -> Result<Option<(C::Channel, DR)>, S::Error> {
+        let others = self.channel_plan.get_other_channels_in_block(&channel, random);
+        for chn in core::iter::once(channel).chain(others.into_iter().flatten()) {
+            let data_rate = match self.channel_data_rate(&chn, frame, now) {
+                Ok(data_rate) => data_rate,
+                Err(reason) => {
+                    trace!("channel {} unavailable: {:?}", chn.get_ul_frequency(), reason);
+                    *unavailable = Some(reason.combine(*unavailable));
+                    continue;
+                }
+            };
+            if let Some(lbt) = R::lbt() {
+                let rf_config = RfConfig {
+                    frequency: chn.get_ul_frequency(),
+                    coding_rate: CodingRate::_4_5,
+                    data_rate: R::convert_data_rate(data_rate)?,
+                };
+                if !sensing.is_channel_clear(&rf_config, lbt).await? {
+                    *unavailable = Some(NoValidChannelReason::ChannelsBusy.combine(*unavailable));
+                    continue;
+                }
+            }
+            return Ok(Some((chn, data_rate)));
+        }
+        Ok(None)
+    }
+
+    /// Send the frame in `buf` on the available channels until a response is received, returning None if no
+    /// response was received after transmitting, or [`Error::NoValidChannelFound`] if no channel was available.
     async fn send_buffer<'a, D: Device>(
-        &'a self,
+        &'a mut self,
         device: &'a mut D,
         buf: &mut [u8],
         tx_len: usize,
         frame: Frame,
-    ) -> Result<Option<(u8, PacketStatus)>, crate::Error<D>> {
+    ) -> Result<Option<(u8, PacketStatus, C::Channel)>, crate::Error<D>> {
+        let mut transmitted = false;
+        let mut unavailable: Option<NoValidChannelReason> = None;
         for trans_index in 0..self.configuration.number_of_transmissions {
             let preferred_join_channel_block = device.preferred_join_channel_block_index();
-            let channels = self
-                .channel_plan
-                .get_send_channels(device.rng(), frame, preferred_join_channel_block)
-                .map_err(crate::device::Error::Rng)?;
-            for channel in channels {
-                if let Some(chn) = channel {
-                    let tx_data_rate = R::override_ul_data_rate_if_necessary(
-                        self.tx_data_rate(),
-                        frame,
-                        chn.get_ul_frequency(),
-                    );
-                    let tx_config = self.create_tx_config(frame, &chn, tx_data_rate)?;
-                    let mdltn_params = device
-                        .radio()
-                        .create_modulation_params(
-                            tx_config.rf.data_rate.spreading_factor,
-                            tx_config.rf.data_rate.bandwidth,
-                            tx_config.rf.coding_rate,
-                            tx_config.rf.frequency,
-                        )
-                        .map_err(crate::device::Error::Radio)?;
-                    let mut tx_pkt_params = device
-                        .radio()
-                        .create_tx_packet_params(8, false, true, false, &mdltn_params)
-                        .map_err(crate::device::Error::Radio)?;
+            let channels = match frame {
+                Frame::Join => {
+                    self.channel_plan.get_join_channels(device.rng(), preferred_join_channel_block)
+                }
+                Frame::Data => self.channel_plan.get_send_channels(
+                    device.rng(),
+                    frame,
+                    preferred_join_channel_block,
+                ),
+            }
+            .map_err(crate::device::Error::Rng)?;
+            for chn in channels.into_iter().flatten() {
+                let now = device.timer().now();
+                let random = device.rng().next_u32().map_err(crate::device::Error::Rng)?;
+                let Some((chn, tx_data_rate)) =
+                    self.select_channel(device, chn, frame, now, random, &mut unavailable).await?
+                else {
+                    continue;
+                };
+                let tx_config = self.create_tx_config(frame, &chn, tx_data_rate)?;
+                match tx_config.rf.data_rate {
+                    Datarate::LoRa { spreading_factor, bandwidth } => {
+                        let mdltn_params = device
+                            .radio()
+                            .create_modulation_params(
+                                spreading_factor,
+                                bandwidth,
+                                tx_config.rf.coding_rate,
+                                tx_config.rf.frequency,
+                            )
+                            .map_err(crate::device::Error::Radio)?;
+                        let mut tx_pkt_params = device
+                            .radio()
+                            .create_tx_packet_params(8, false, true, false, &mdltn_params)
+                            .map_err(crate::device::Error::Radio)?;
 
-                    trace!("tx config {:?}", tx_config);
-                    device
-                        .radio()
-                        .prepare_for_tx(
-                            &mdltn_params,
-                            &mut tx_pkt_params,
-                            tx_config.pw as i32,
-                            &buf[..tx_len],
-                        )
-                        .await
-                        .map_err(crate::device::Error::Radio)?;
-                    device.radio().tx().await.map_err(crate::device::Error::Radio)?;
-                    device.timer().reset();
-                    trace!("SENT");
-                    match self.rx_with_timeout(frame, device, buf, tx_data_rate, &chn).await {
-                        Ok(Some(ret)) => {
-                            return Ok(Some(ret));
-                        }
-                        Ok(None) => {
-                            if frame == Frame::Data {
-                                if (trans_index + 1) >= self.configuration.number_of_transmissions {
-                                    return Ok(None);
-                                } else {
-                                    break;
-                                }
+                        trace!("tx config {:?}", tx_config);
+                        device
+                            .radio()
+                            .prepare_for_tx(
+                                &mdltn_params,
+                                &mut tx_pkt_params,
+                                tx_config.pw as i32,
+                                &buf[..tx_len],
+                            )
+                            .await
+                            .map_err(crate::device::Error::Radio)?;
+                        device.radio().tx().await.map_err(crate::device::Error::Radio)?;
+                    }
+                    Datarate::Fsk { .. } => {
+                        trace!("tx config {:?}", tx_config);
+                        device
+                            .fsk_tx(&tx_config, &buf[..tx_len])
+                            .await
+                            .map_err(crate::device::Error::Radio)?;
+                    }
+                    Datarate::LrFhss { .. } => {
+                        trace!("tx config {:?}", tx_config);
+                        device
+                            .lr_fhss_tx(&tx_config, &buf[..tx_len])
+                            .await
+                            .map_err(crate::device::Error::Radio)?;
+                    }
+                }
+                device.timer().reset();
+                let tx_end = device.timer().now();
+                self.last_tx_end = Some(tx_end);
+                transmitted = true;
+                let time_on_air =
+                    (R::time_on_air_us(tx_data_rate, tx_len as u8)? as u64).div_ceil(1000);
+                self.duty_cycle.record_tx(
+                    R::sub_bands(),
+                    self.configuration.max_duty_cycle,
+                    tx_config.rf.frequency,
+                    tx_end,
+                    time_on_air,
+                );
+                if frame == Frame::Join {
+                    self.configuration.join_back_off.record_tx(tx_end, time_on_air);
+                } else {
+                    self.handle_uplink_sent();
+                }
+                trace!("SENT");
+                match self.rx_with_timeout(frame, device, buf, tx_data_rate, &chn).await {
+                    Ok(Some((rx_len, rx_quality))) => {
+                        return Ok(Some((rx_len, rx_quality, chn)));
+                    }
+                    Ok(None) => {
+                        if frame == Frame::Data {
+                            if (trans_index + 1) >= self.configuration.number_of_transmissions {
+                                return Ok(None);
+                            } else {
+                                break;
                             }
                         }
-                        Err(e) => {
-                            if frame == Frame::Data {
-                                if (trans_index + 1) >= self.configuration.number_of_transmissions {
-                                    return Err(e);
-                                } else {
-                                    break;
-                                }
+                    }
+                    Err(e) => {
+                        if frame == Frame::Data {
+                            if (trans_index + 1) >= self.configuration.number_of_transmissions {
+                                return Err(e);
+                            } else {
+                                break;
                             }
                         }
                     }
                 }
-
-                // Delay for a random amount of time between 1 and 2 seconds ???
-                let random = device.rng().next_u32().map_err(crate::device::Error::Rng)?;
-                let delay_ms = 1000 + (random % 1000);
-                device.timer().reset();
-                device.timer().at(delay_ms as u64).await.map_err(crate::device::Error::Timer)?;
             }
         }
-        Ok(None)
+        if transmitted {
+            Ok(None)
+        } else {
+            // every channel was masked, incompatible with the data rate, in time-off or occupied
+            Err(crate::Error::Mac(Error::NoValidChannelFound(
+                unavailable.unwrap_or(NoValidChannelReason::ChannelMask),
+            )))
+        }
     }
 
-    /// Establish a session between the end device and a network server.
+    /// Establish a session between the end device and a network server.  Join-requests are subject to the
+    /// retransmission back-off of LoRaWAN 1.0.4 across calls and power cycles, returning
+    /// [`Error::NoValidChannelFound`] with [`NoValidChannelReason::DutyCycle`] until the next join-request is
+    /// allowed.
     pub async fn join<'a, D: Device>(
         &'a mut self,
         device: &'a mut D,
         buf: &'a mut [u8],
     ) -> Result<(), crate::Error<D>> {
+        self.check_region()?;
+        let now = device.timer().now();
+        if let Some(wait) = self.configuration.join_back_off.time_off(now) {
+            return Err(crate::Error::Mac(Error::NoValidChannelFound(
+                NoValidChannelReason::DutyCycle(wait),
+            )));
+        }
         self.credentials.incr_dev_nonce();
         device
-            .persist_to_non_volatile(&self.configuration, &self.credentials)
+            .persist_to_non_volatile(&self.configuration, &self.credentials, self.session.as_ref())
             .map_err(crate::device::Error::NonVolatileStore)?;
         let len = self.create_join_request(buf)?;
         let rx_res = self.send_buffer(device, buf, len, Frame::Join).await?;
-        if let Some((rx_len, _)) = rx_res {
+        // keep the join back-off through power cycles
+        device
+            .persist_to_non_volatile(&self.configuration, &self.credentials, self.session.as_ref())
+            .map_err(crate::device::Error::NonVolatileStore)?;
+        if let Some((rx_len, _, channel)) = rx_res {
             let encrypted = EncryptedJoinAcceptPayload::new(&mut buf[..rx_len as usize])
                 .map_err(|e| crate::Error::<D>::Mac(Error::Encoding(e)))?;
 
             let decrypted = encrypted.decrypt(&self.credentials.app_key, &DefaultFactory);
 
             if decrypted.validate_mic(&self.credentials.app_key, &DefaultFactory) {
+                self.channel_plan.handle_join_accepted(&channel);
+                self.configuration.join_back_off.reset();
                 let session = Session::derive_new(
                     &decrypted,
                     DevNonce::<[u8; 2]>::new(self.credentials.dev_nonce.to_le_bytes()).unwrap(),
@@ -767,8 +1444,13 @@ where
                 if let Some(cf_list) = decrypted.c_f_list() {
                     self.channel_plan.handle_cf_list(cf_list)?;
                 }
+                self.update_session_channel_plan();
                 device
-                    .persist_to_non_volatile(&self.configuration, &self.credentials)
+                    .persist_to_non_volatile(
+                        &self.configuration,
+                        &self.credentials,
+                        self.session.as_ref(),
+                    )
                     .map_err(crate::device::Error::NonVolatileStore)?;
                 Ok(())
             } else {
@@ -779,7 +1461,99 @@ where
         }
     }
 
+    /// Establish a session between the end device and a network server using Activation By Personalization,
+    /// without a join exchange.  `devaddr` uses the same byte order as [`DevAddr`].  Frame counters persisted
+    /// for the same DevAddr are resumed, since the network server rejects frame counters which were already used.
+    pub fn activate_by_personalization<D: Device>(
+        &mut self,
+        device: &mut D,
+        devaddr: [u8; 4],
+        nwkskey: [u8; 16],
+        appskey: [u8; 16],
+    ) -> Result<(), crate::Error<D>> {
+        self.check_region()?;
+        let mut session =
+            Session::new(NwkSKey::from(nwkskey), AppSKey::from(appskey), DevAddr::from(devaddr));
+        if let Ok(storable) = device.non_volatile_store().load() {
+            if storable.devaddr == Some(devaddr) {
+                // uplink frame counter writes are deferred, so skip the counts which may have been used; the
+                // downlink frame counter is written whenever it changes
+                session.fcnt_up =
+                    storable.fcnt_up.saturating_add(device.frame_counter_persist_interval());
+                session.fcnt_down = storable.fcnt_down;
+            }
+        }
+        self.session.replace(session);
+        device
+            .persist_to_non_volatile(&self.configuration, &self.credentials, self.session.as_ref())
+            .map_err(crate::device::Error::NonVolatileStore)?;
+        Ok(())
+    }
+
+    /// Check that the channel plan was created for the region selected at runtime, if any, as a channel plan created
+    /// before another region was selected cannot be used.
+    fn check_region(&self) -> Result<(), Error> {
+        match (self.channel_plan.selected_region(), AnyRegion::selected()) {
+            (Some(_), None) => Err(Error::RegionNotSelected),
+            (Some(region), Some(selected)) if region != selected => Err(Error::RegionMismatch),
+            _ => Ok(()),
+        }
+    }
+
+    /// Advance the uplink frame counter, and the ADR back-off if `adr` is set, for an uplink carrying `len` bytes of
+    /// application data.  Nothing changes if the uplink cannot be sent.
+    fn start_uplink<D: DeviceSpecs>(&mut self, len: usize, adr: bool) -> Result<(), Error> {
+        self.check_region()?;
+        match &self.session {
+            Some(session) if session.is_expired() => return Err(Error::SessionExpired),
+            Some(_) => {}
+            None => return Err(Error::NetworkNotJoined),
+        }
+        // the ADR back-off lowers the data rate before this uplink is sent
+        let mut data_rate = self.configuration.tx_data_rate;
+        if adr && data_rate.is_some() && self.is_adr_back_off_due::<D>() {
+            data_rate = R::next_adr_data_rate(data_rate);
+        }
+        let data_rate = data_rate.unwrap_or(R::default_data_rate());
+        if len > self.max_payload_size_at(data_rate).map_err(|_| Error::UnsupportedDataRate)? {
+            return Err(Error::PayloadSizeExceeded);
+        }
+        if let Some(session) = &mut self.session {
+            session.fcnt_up_increment();
+        }
+        if adr {
+            self.adr_back_off::<D>();
+        }
+        Ok(())
+    }
+
+    /// Clear the acknowledgement and the MAC commands carried by a data uplink once it has been transmitted.
+    fn handle_uplink_sent(&mut self) {
+        self.ack_next = false;
+        // Some commands have different ack meechanism
+        // ACK needs to be sent until there is a downlink
+        self.uplink_cmds.retain(|cmd| {
+            matches!(
+                cmd,
+                UplinkMacCommandCreator::RXParamSetupAns(_)
+                    | UplinkMacCommandCreator::RXTimingSetupAns(_)
+                    | UplinkMacCommandCreator::DlChannelAns(_)
+                    | UplinkMacCommandCreator::TXParamSetupAns(_)
+            )
+        });
+        self.class_b.uplink_cmds.retain(|cmd| {
+            matches!(
+                cmd,
+                class_b::UplinkCommand::PingSlotInfoReq { .. }
+                    | class_b::UplinkCommand::PingSlotChannelAns { .. }
+            )
+        });
+    }
+
     /// Send data from the end device to a network server on an established session.
+    ///
+    /// The RX1 and RX2 windows close at the Class A times in every class; in Class C, listen for further downlinks
+    /// with [`Mac::receive`].
     pub async fn send<'a, D: Device>(
         &mut self,
         device: &mut D,
@@ -788,19 +1562,13 @@ where
         fport: u8,
         mut confirmed: bool,
     ) -> Result<Option<(FRMPayload<'a>, PacketStatus)>, crate::Error<D>> {
-        if let Some(ref mut session) = self.session {
-            if !session.is_expired() {
-                session.fcnt_up_increment();
-                if device.adaptive_data_rate_enabled() {
-                    self.adr_back_off::<D>();
-                }
-            } else {
-                return Err(crate::Error::Mac(crate::mac::Error::SessionExpired));
-            }
-        } else {
-            return Err(crate::Error::Mac(crate::mac::Error::NetworkNotJoined));
-        }
-        if !self.uplink_cmds.is_empty() {
+        self.start_uplink::<D>(data.len(), device.adaptive_data_rate_enabled())?;
+        // persist the frame counter before use, so it is never reused after a power cycle
+        // (writes are deferred within the device's frame counter persist interval)
+        device
+            .persist_to_non_volatile(&self.configuration, &self.credentials, self.session.as_ref())
+            .map_err(crate::device::Error::NonVolatileStore)?;
+        if !self.uplink_cmds.is_empty() || !self.class_b.uplink_cmds.is_empty() {
             confirmed = true;
         }
         let len = self.prepare_buffer::<D>(
@@ -811,88 +1579,312 @@ where
             device.adaptive_data_rate_enabled(),
         )?;
         let rx_res = self.send_buffer(device, buf, len, Frame::Data).await?;
-        self.ack_next = false;
-        // Some commands have different ack meechanism
-        // ACK needs to be sent until there is a downlink
-        self.uplink_cmds.retain(|cmd| {
-            matches!(
-                cmd,
-                UplinkMacCommandCreator::RXParamSetupAns(_)
-                    | UplinkMacCommandCreator::RXTimingSetupAns(_)
-                    | UplinkMacCommandCreator::DlChannelAns(_)
-                    | UplinkMacCommandCreator::TXParamSetupAns(_)
-            )
-        });
-        // Handle received data
-        if let Some(ref mut session) = self.session {
-            // Parse payload and copy into user bufer is provided
-            if let Some((rx_len, rx_quality)) = rx_res {
-                let res = parse(&mut buf[..rx_len as usize]);
-                if let Ok(PhyPayload::Data(encoding::parser::DataPayload::Encrypted(_))) = res {
-                    session.adr_ack_cnt_clear();
-                } else {
-                    session.adr_ack_cnt_increment();
+        if let Some((rx_len, rx_quality, _)) = rx_res {
+            self.handle_downlink(device, buf, rx_len, rx_quality).map(Some)
+        } else if confirmed {
+            Err(crate::Error::Mac(Error::NoResponse))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Send the pending MAC commands, such as those queued by [`Mac::request_link_check`] and
+    /// [`Mac::request_device_time`], immediately in an uplink on FPort 0 without application data.
+    pub async fn send_mac_commands<'a, D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &'a mut [u8],
+    ) -> Result<Option<(FRMPayload<'a>, PacketStatus)>, crate::Error<D>> {
+        self.send(device, buf, &[], 0, false).await
+    }
+
+    /// Receive a downlink while operating in Class B or Class C.
+    ///
+    /// In Class C the radio listens continuously on the RX2 frequency and data rate until a downlink addressed to
+    /// the end device arrives.  In Class B the radio listens in each ping slot, also tracking the beacon at the
+    /// start of every beacon period; if the beacon is lost for too long, the end device reverts to Class A and
+    /// [`Error::BeaconLost`] is returned.  Call this between uplinks to keep listening; drop the returned future
+    /// (for example by racing it against an application event) to stop listening before calling [`Mac::send`].
+    pub async fn receive<'a, D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &'a mut [u8],
+    ) -> Result<(FRMPayload<'a>, PacketStatus), crate::Error<D>> {
+        if self.class == Class::A {
+            return Err(crate::Error::Mac(Error::ClassNotEnabled));
+        }
+        self.check_region()?;
+        if !self.is_joined() {
+            return Err(crate::Error::Mac(Error::NetworkNotJoined));
+        }
+        if self.class == Class::B {
+            return self.receive_ping_slots(device, buf).await;
+        }
+        let rf_config = self.create_rx2_rf_config(None)?;
+        debug!("rf config RXC {:?}", rf_config);
+        let (rx_len, rx_quality) = self.rx_continuous(device, &rf_config, buf).await?;
+        self.handle_downlink(device, buf, rx_len, rx_quality)
+    }
+
+    /// Listen continuously with the RX2 settings until a frame addressed to the end device is received.
+    async fn rx_continuous<D: Device>(
+        &self,
+        device: &mut D,
+        rf_config: &RfConfig,
+        buf: &mut [u8],
+    ) -> Result<(u8, PacketStatus), crate::Error<D>> {
+        loop {
+            let prepared_rx = prepare_for_rx(device, rf_config, RxMode::Continuous, None).await?;
+            let (rx_len, rx_quality) = rx(device, rf_config, &prepared_rx, buf)
+                .await
+                .map_err(crate::device::Error::Radio)?;
+            if self.is_for_session(&mut buf[..rx_len as usize]) {
+                return Ok((rx_len, rx_quality));
+            }
+            trace!("RXC: ignoring frame not addressed to this end device");
+        }
+    }
+
+    /// Search for the network beacon and, once received, switch to Class B.
+    ///
+    /// If a BeaconTimingAns was received since the last search, the receive window is opened just before the
+    /// announced beacon; otherwise the radio listens continuously, which may take up to a beacon period (or,
+    /// for regions where the beacon hops over several channels, one period per channel).  Drop the returned
+    /// future to abandon the search.
+    pub async fn acquire_beacon<D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &mut [u8],
+    ) -> Result<(Beacon, PacketStatus), crate::Error<D>> {
+        if !self.is_joined() {
+            return Err(crate::Error::Mac(Error::NetworkNotJoined));
+        }
+        let beacon_len = Beacon::size(R::beacon_rfu_lengths());
+        if let Some(timing) = self.class_b.beacon_timing.take() {
+            let rf_config = self.create_beacon_rf_config(timing.channel)?;
+            debug!("rf config beacon {:?}", rf_config);
+            let widening = class_b::BEACON_WINDOW_WIDENING_MS + class_b::PING_SLOT_LEN_MS;
+            if timing.at > device.timer().now() + widening {
+                Self::wait_until(device, timing.at - widening).await?;
+                let rx_mode = Self::beacon_window_mode(&rf_config, widening);
+                if let Some(res) =
+                    self.receive_beacon(device, buf, &rf_config, rx_mode, beacon_len).await?
+                {
+                    return Ok(res);
                 }
-                match res {
-                    Ok(PhyPayload::Data(encoding::parser::DataPayload::Encrypted(encrypted))) => {
-                        if session.devaddr() != &encrypted.fhdr().dev_addr() {
-                            return Err(crate::Error::Mac(crate::mac::Error::InvalidDevAddr));
-                        }
-                        // clear all uplink cmds here after successfull downlink
-                        self.uplink_cmds.clear();
-                        let fcnt = encrypted.fhdr().fcnt() as u32;
-                        // use temporary variable for ack_next to only confirm if the message was correctly handled
-                        let ack_next = encrypted.is_confirmed();
-                        if !encrypted.validate_mic(session.nwkskey().inner(), fcnt, &DefaultFactory)
-                        {
-                            return Err(crate::Error::Mac(crate::mac::Error::InvalidMic));
-                        }
-                        if !(fcnt > session.fcnt_down || fcnt == 0) {
-                            trace!("Invalid fcnt {} {}", fcnt, session.fcnt_down);
-                            return Err(crate::Error::Mac(crate::mac::Error::InvalidFcnt));
-                        }
-                        session.fcnt_down = fcnt;
-
-                        let decrypted = encrypted
-                            .decrypt(
-                                Some(session.nwkskey().inner()),
-                                Some(session.appskey().inner()),
-                                session.fcnt_down,
-                                &DefaultFactory,
-                            )
-                            .map_err(|e| crate::Error::<D>::Mac(Error::Encoding(e)))?;
-
-                        //trace!("fhdr {:?}", decrypted.fhdr());
-                        self.handle_downlink_macs(
-                            device,
-                            rx_quality,
-                            MacCommandIterator::new(decrypted.fhdr().data()),
-                        )?;
-                        let payload = frm_payload(decrypted);
-                        if let FRMPayload::MACCommands(mac_cmds) = &payload {
-                            self.handle_downlink_macs(
-                                device,
-                                rx_quality,
-                                MacCommandIterator::new(mac_cmds.data()),
-                            )?;
-                        }
-                        device
-                            .persist_to_non_volatile(&self.configuration, &self.credentials)
-                            .map_err(crate::device::Error::NonVolatileStore)?;
+            }
+        }
+        let rf_config = self.create_beacon_rf_config(0)?;
+        debug!("rf config beacon {:?}", rf_config);
+        loop {
+            if let Some(res) =
+                self.receive_beacon(device, buf, &rf_config, RxMode::Continuous, beacon_len).await?
+            {
+                return Ok(res);
+            }
+        }
+    }
 
-                        self.ack_next = ack_next;
-                        Ok(Some((payload, rx_quality)))
-                    }
-                    Ok(_) => Err(crate::Error::Mac(crate::mac::Error::InvalidPayloadType)),
-                    Err(e) => Err(crate::Error::Mac(Error::Encoding(e))),
+    /// Receive a beacon and lock on to its beacon period, returning None if no valid beacon was received.
+    async fn receive_beacon<D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &mut [u8],
+        rf_config: &RfConfig,
+        rx_mode: RxMode,
+        beacon_len: usize,
+    ) -> Result<Option<(Beacon, PacketStatus)>, crate::Error<D>> {
+        let prepared_rx =
+            prepare_for_rx(device, rf_config, rx_mode, Some(beacon_len as u8)).await?;
+        let (rx_len, rx_quality) = match rx(device, rf_config, &prepared_rx, buf).await {
+            Ok(ret) => ret,
+            Err(_e) => return Ok(None),
+        };
+        let rx_end = device.timer().now();
+        match Beacon::parse(&buf[..rx_len as usize], R::beacon_rfu_lengths()) {
+            Some(beacon) => {
+                trace!("beacon {:?}", beacon);
+                let time_on_air = rf_config.lora_modulation_params().map_or(0, |bb| {
+                    bb.time_on_air_us(
+                        Some(class_b::BEACON_PREAMBLE_SYMBOLS as u8),
+                        false,
+                        beacon_len as u8,
+                    ) as u64
+                        / 1000
+                });
+                self.class_b.beacon = Some(BeaconState {
+                    time: beacon.time,
+                    period_start: rx_end.saturating_sub(time_on_air),
+                    missed: 0,
+                });
+                self.class = Class::B;
+                Ok(Some((beacon, rx_quality)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get the receive mode for a beacon window opened `widening` milliseconds before the expected beacon.
+    fn beacon_window_mode(rf_config: &RfConfig, widening: u64) -> RxMode {
+        let widening_symbols = rf_config
+            .lora_modulation_params()
+            .map_or(0, |bb| bb.delay_in_symbols(2 * widening as u32));
+        RxMode::Single(class_b::BEACON_PREAMBLE_SYMBOLS + widening_symbols)
+    }
+
+    /// Wait until the timer reaches `at`, as reported by [`Timer::now`].
+    async fn wait_until<D: Device>(device: &mut D, at: u64) -> Result<(), crate::Error<D>> {
+        let now = device.timer().now();
+        device.timer().reset();
+        device.timer().at(at.saturating_sub(now)).await.map_err(crate::device::Error::Timer)?;
+        Ok(())
+    }
+
+    /// Listen in the Class B ping slots until a downlink addressed to the end device arrives.
+    async fn receive_ping_slots<'a, D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &'a mut [u8],
+    ) -> Result<(FRMPayload<'a>, PacketStatus), crate::Error<D>> {
+        let mut dev_addr = [0u8; 4];
+        if let Some(session) = &self.session {
+            dev_addr.copy_from_slice(session.devaddr().as_ref());
+        }
+        loop {
+            let beacon = self.class_b.beacon.ok_or(Error::BeaconNotLocked)?;
+            let periodicity = self.class_b.periodicity();
+            let ping_offset = class_b::ping_offset(beacon.time, dev_addr, periodicity);
+            let now = device.timer().now();
+            let Some(ping_slot) = beacon.next_ping_slot(now, ping_offset, periodicity) else {
+                self.track_beacon(device, buf).await?;
+                continue;
+            };
+            let rf_config = self.create_ping_slot_rf_config(beacon.time, dev_addr)?;
+            Self::wait_until(device, ping_slot).await?;
+            let prepared_rx =
+                prepare_for_rx(device, &rf_config, Self::rx_window_mode(&rf_config), None).await?;
+            if let Ok((rx_len, rx_quality)) = rx(device, &rf_config, &prepared_rx, buf).await {
+                if self.is_for_session(&mut buf[..rx_len as usize]) {
+                    return self.handle_downlink(device, buf, rx_len, rx_quality);
                 }
-            } else if confirmed {
-                Err(crate::Error::Mac(Error::NoResponse))
+                trace!("ping slot: ignoring frame not addressed to this end device");
+            }
+        }
+    }
+
+    /// Receive the beacon at the start of the next beacon period.  A missed beacon is assumed to have been sent
+    /// on time, with the receive window widened for each consecutive miss, until the beacon is considered lost.
+    async fn track_beacon<D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &mut [u8],
+    ) -> Result<(), crate::Error<D>> {
+        let mut state = self.class_b.beacon.ok_or(Error::BeaconNotLocked)?;
+        let expected = state.period_start + class_b::BEACON_PERIOD_MS;
+        let widening = class_b::BEACON_WINDOW_WIDENING_MS * (state.missed as u64 + 1);
+        let beacon_time = state.time.wrapping_add((class_b::BEACON_PERIOD_MS / 1000) as u32);
+        let mut received = false;
+        if device.timer().now() + widening < expected {
+            let channel = class_b::channel_index(beacon_time, 0, R::beacon_channels());
+            let rf_config = self.create_beacon_rf_config(channel)?;
+            Self::wait_until(device, expected - widening).await?;
+            let rx_mode = Self::beacon_window_mode(&rf_config, widening);
+            let beacon_len = Beacon::size(R::beacon_rfu_lengths());
+            received =
+                self.receive_beacon(device, buf, &rf_config, rx_mode, beacon_len).await?.is_some();
+        }
+        if !received {
+            state.missed += 1;
+            debug!("beacon missed {}", state.missed);
+            if state.missed > class_b::MAX_MISSED_BEACONS {
+                self.class_b.beacon = None;
+                self.class = Class::A;
+                return Err(crate::Error::Mac(Error::BeaconLost));
+            }
+            state.time = beacon_time;
+            state.period_start = expected;
+            self.class_b.beacon = Some(state);
+        }
+        Ok(())
+    }
+
+    /// Is the received frame a data downlink for the current session?  Other end devices
+    /// share the RX2 channel, so their frames are heard during Class C reception.
+    fn is_for_session(&self, rx_buf: &mut [u8]) -> bool {
+        match (&self.session, parse(rx_buf)) {
+            (
+                Some(session),
+                Ok(PhyPayload::Data(encoding::parser::DataPayload::Encrypted(encrypted))),
+            ) => session.devaddr() == &encrypted.fhdr().dev_addr(),
+            _ => false,
+        }
+    }
+
+    /// Validate, decrypt and process a received downlink, returning its FRMPayload.
+    fn handle_downlink<'a, D: Device>(
+        &mut self,
+        device: &mut D,
+        buf: &'a mut [u8],
+        rx_len: u8,
+        rx_quality: PacketStatus,
+    ) -> Result<(FRMPayload<'a>, PacketStatus), crate::Error<D>> {
+        if let Some(ref mut session) = self.session {
+            let res = parse(&mut buf[..rx_len as usize]);
+            if let Ok(PhyPayload::Data(encoding::parser::DataPayload::Encrypted(_))) = res {
+                session.adr_ack_cnt_clear();
             } else {
-                Ok(None)
+                session.adr_ack_cnt_increment();
+            }
+            match res {
+                Ok(PhyPayload::Data(encoding::parser::DataPayload::Encrypted(encrypted))) => {
+                    if session.devaddr() != &encrypted.fhdr().dev_addr() {
+                        return Err(crate::Error::Mac(crate::mac::Error::InvalidDevAddr));
+                    }
+                    // clear all uplink cmds here after successfull downlink
+                    self.uplink_cmds.clear();
+                    self.class_b.uplink_cmds.clear();
+                    let fcnt = encrypted.fhdr().fcnt() as u32;
+                    // use temporary variable for ack_next to only confirm if the message was correctly handled
+                    let ack_next = encrypted.is_confirmed();
+                    if !encrypted.validate_mic(session.nwkskey().inner(), fcnt, &DefaultFactory) {
+                        return Err(crate::Error::Mac(crate::mac::Error::InvalidMic));
+                    }
+                    if !(fcnt > session.fcnt_down || fcnt == 0) {
+                        trace!("Invalid fcnt {} {}", fcnt, session.fcnt_down);
+                        return Err(crate::Error::Mac(crate::mac::Error::InvalidFcnt));
+                    }
+                    session.fcnt_down = fcnt;
+
+                    let decrypted = encrypted
+                        .decrypt(
+                            Some(session.nwkskey().inner()),
+                            Some(session.appskey().inner()),
+                            session.fcnt_down,
+                            &DefaultFactory,
+                        )
+                        .map_err(|e| crate::Error::<D>::Mac(Error::Encoding(e)))?;
+
+                    //trace!("fhdr {:?}", decrypted.fhdr());
+                    self.handle_downlink_mac_bytes(device, rx_quality, decrypted.fhdr().data())?;
+                    let payload = frm_payload(decrypted);
+                    if let FRMPayload::MACCommands(mac_cmds) = &payload {
+                        self.handle_downlink_mac_bytes(device, rx_quality, mac_cmds.data())?;
+                    }
+                    device
+                        .persist_to_non_volatile(
+                            &self.configuration,
+                            &self.credentials,
+                            self.session.as_ref(),
+                        )
+                        .map_err(crate::device::Error::NonVolatileStore)?;
+
+                    self.ack_next = ack_next;
+                    Ok((payload, rx_quality))
+                }
+                Ok(_) => Err(crate::Error::Mac(crate::mac::Error::InvalidPayloadType)),
+                Err(e) => Err(crate::Error::Mac(Error::Encoding(e))),
             }
         } else {
-            //Should never end up here
             Err(crate::Error::Mac(Error::NetworkNotJoined))
         }
     }
@@ -921,17 +1913,27 @@ pub(crate) mod tests {
     use core::convert::Infallible;
 
     use encoding::keys::{AppSKey, NwkSKey};
-    use encoding::maccommands::{LinkADRAnsPayload, UplinkMacCommandCreator};
-    use encoding::parser::DevAddr;
+    use encoding::maccommands::{Frequency, LinkADRAnsPayload, UplinkMacCommandCreator};
+    use encoding::parser::{CfList, DevAddr};
+    use lora_modulation::{Bandwidth, SpreadingFactor};
 
     use super::*;
     use crate::device::rng::Rng;
+    use crate::device::types::LrFhssCodingRate;
     use crate::device::DeviceSpecs;
+    use crate::mac::region::as923::AS923_1;
+    use crate::mac::region::au915::AU915;
     use crate::mac::region::channel_plan::dynamic::DynamicChannelPlan;
     use crate::mac::region::channel_plan::fixed::FixedChannelPlan;
     use crate::mac::region::channel_plan::ChannelPlan;
+    use crate::mac::region::cn779::CN779;
+    use crate::mac::region::eu433::EU433;
     use crate::mac::region::eu868::EU868;
+    use crate::mac::region::in865::IN865;
+    use crate::mac::region::kr920::KR920;
+    use crate::mac::region::ru864::RU864;
     use crate::mac::region::us915::US915;
+    use crate::mac::region::Region;
     use crate::mac::{Credentials, Frame, Mac};
 
     struct DeviceSpecsMock;
@@ -946,6 +1948,50 @@ pub(crate) mod tests {
             Ok(rng.gen())
         }
     }
+    /// Reports the channel at `busy_frequency` as occupied, recording the frequencies sensed.
+    struct ChannelSensingMock {
+        busy_frequency: u32,
+        sensed: std::vec::Vec<u32>,
+    }
+    impl ChannelSensing for ChannelSensingMock {
+        type Error = region::Error;
+
+        async fn is_channel_clear(
+            &mut self,
+            rf_config: &RfConfig,
+            _lbt: ListenBeforeTalk,
+        ) -> Result<bool, Self::Error> {
+            self.sensed.push(rf_config.frequency);
+            Ok(rf_config.frequency != self.busy_frequency)
+        }
+    }
+    struct WindowReceptionMock {
+        opened: std::vec::Vec<(Window, u32, u64)>,
+    }
+    impl WindowReception for WindowReceptionMock {
+        type Error = region::Error;
+
+        async fn receive_in_window(
+            &mut self,
+            window: &Window,
+            rf_config: &RfConfig,
+            _rx_mode: RxMode,
+            open_ms: u64,
+            _buf: &mut [u8],
+        ) -> Result<Option<(u8, PacketStatus)>, Self::Error> {
+            self.opened.push((*window, rf_config.frequency, open_ms));
+            Ok(None)
+        }
+    }
+    fn block_on<F: core::future::Future>(future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+        loop {
+            if let core::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
     #[test]
     fn validate_frequency() {
         assert!(Mac::<EU868, DynamicChannelPlan<EU868>>::validate_frequency::<DeviceSpecsMock>(
@@ -966,6 +2012,30 @@ pub(crate) mod tests {
         assert!(!Mac::<US915, FixedChannelPlan<US915>>::validate_frequency::<DeviceSpecsMock>(
             928_000_001
         ));
+        assert!(Mac::<IN865, DynamicChannelPlan<IN865>>::validate_frequency::<DeviceSpecsMock>(
+            865_000_000
+        ));
+        assert!(!Mac::<IN865, DynamicChannelPlan<IN865>>::validate_frequency::<DeviceSpecsMock>(
+            867_000_001
+        ));
+        assert!(Mac::<RU864, DynamicChannelPlan<RU864>>::validate_frequency::<DeviceSpecsMock>(
+            864_000_000
+        ));
+        assert!(!Mac::<RU864, DynamicChannelPlan<RU864>>::validate_frequency::<DeviceSpecsMock>(
+            870_000_001
+        ));
+        assert!(Mac::<EU433, DynamicChannelPlan<EU433>>::validate_frequency::<DeviceSpecsMock>(
+            434_665_000
+        ));
+        assert!(!Mac::<EU433, DynamicChannelPlan<EU433>>::validate_frequency::<DeviceSpecsMock>(
+            433_174_999
+        ));
+        assert!(Mac::<CN779, DynamicChannelPlan<CN779>>::validate_frequency::<DeviceSpecsMock>(
+            786_500_000
+        ));
+        assert!(!Mac::<CN779, DynamicChannelPlan<CN779>>::validate_frequency::<DeviceSpecsMock>(
+            779_499_999
+        ));
     }
 
     #[test]
@@ -988,6 +2058,107 @@ pub(crate) mod tests {
         assert!(!Mac::<US915, DynamicChannelPlan<US915>>::validate_rx1_data_rate_offset::<
             DeviceSpecsMock,
         >(4));
+        assert!(Mac::<IN865, DynamicChannelPlan<IN865>>::validate_rx1_data_rate_offset::<
+            DeviceSpecsMock,
+        >(7));
+        assert!(!Mac::<IN865, DynamicChannelPlan<IN865>>::validate_rx1_data_rate_offset::<
+            DeviceSpecsMock,
+        >(8));
+        assert!(Mac::<RU864, DynamicChannelPlan<RU864>>::validate_rx1_data_rate_offset::<
+            DeviceSpecsMock,
+        >(5));
+        assert!(!Mac::<RU864, DynamicChannelPlan<RU864>>::validate_rx1_data_rate_offset::<
+            DeviceSpecsMock,
+        >(6));
+        assert!(!Mac::<EU433, DynamicChannelPlan<EU433>>::validate_rx1_data_rate_offset::<
+            DeviceSpecsMock,
+        >(6));
+        assert!(!Mac::<CN779, DynamicChannelPlan<CN779>>::validate_rx1_data_rate_offset::<
+            DeviceSpecsMock,
+        >(6));
+    }
+
+    #[test]
+    fn fsk_data_rate() {
+        struct FskDeviceSpecsMock;
+        impl DeviceSpecs for FskDeviceSpecsMock {
+            fn supports_fsk() -> bool {
+                true
+            }
+        }
+        type MacEU868 = Mac<EU868, DynamicChannelPlan<EU868>>;
+        assert!(matches!(EU868::convert_data_rate(DR::_7), Ok(Datarate::Fsk { bit_rate: 50_000 })));
+        assert!(MacEU868::validate_data_rate::<DeviceSpecsMock>(6));
+        assert!(!MacEU868::validate_data_rate::<DeviceSpecsMock>(7));
+        assert!(MacEU868::validate_data_rate::<FskDeviceSpecsMock>(7));
+        assert!(!MacEU868::validate_data_rate::<FskDeviceSpecsMock>(8));
+        // preamble, sync word, length, 20 byte PHYPayload and CRC at 50 kbps
+        assert_eq!(EU868::time_on_air_us(DR::_7, 20).unwrap(), 4_960);
+        assert_eq!(EU868::get_rx1_dr(DR::_7, 0).unwrap(), DR::_7);
+        assert_eq!(EU868::get_rx1_dr(DR::_6, 5).unwrap(), DR::_1);
+        assert_eq!(EU868::next_adr_data_rate(Some(DR::_7)), Some(DR::_6));
+        let rf_config = RfConfig {
+            frequency: 868_300_000,
+            coding_rate: CodingRate::_4_5,
+            data_rate: EU868::convert_data_rate(DR::_7).unwrap(),
+        };
+        assert!(rf_config.lora_modulation_params().is_none());
+    }
+
+    #[test]
+    fn cf_list_channel_data_rate() {
+        let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let zero = [0u8; 3];
+        let frequencies = [[0x18, 0x4f, 0x84], zero, zero, zero, zero];
+        let cf_list =
+            CfList::DynamicChannel(frequencies.each_ref().map(|f| Frequency::new(f).unwrap()));
+        mac_eu868.channel_plan.handle_cf_list(cf_list).unwrap();
+        let chn = mac_eu868.channel_plan.get_channel(3).unwrap();
+        assert_eq!(chn.get_ul_frequency(), 867_100_000);
+        assert_eq!(chn.get_ul_data_rate_range(), (DR::_0, DR::_5));
+        // FSK is only used on a channel opened for it through NewChannelReq
+        mac_eu868.configuration.tx_data_rate = Some(DR::_7);
+        assert_eq!(
+            mac_eu868.channel_data_rate(&chn, Frame::Data, 1_000),
+            Err(NoValidChannelReason::DataRate(DR::_7))
+        );
+        mac_eu868.configuration.tx_data_rate = Some(DR::_5);
+        assert_eq!(mac_eu868.channel_data_rate(&chn, Frame::Data, 1_000), Ok(DR::_5));
+    }
+
+    #[test]
+    fn lr_fhss_data_rate() {
+        struct LrFhssDeviceSpecsMock;
+        impl DeviceSpecs for LrFhssDeviceSpecsMock {
+            fn supports_lr_fhss() -> bool {
+                true
+            }
+        }
+        type MacEU868 = Mac<EU868, DynamicChannelPlan<EU868>>;
+        type MacUS915 = Mac<US915, FixedChannelPlan<US915>>;
+        assert!(matches!(
+            EU868::convert_data_rate(DR::_8),
+            Ok(Datarate::LrFhss { coding_rate: LrFhssCodingRate::_1_3, bandwidth: 137_000, .. })
+        ));
+        assert!(matches!(
+            US915::convert_data_rate(DR::_6),
+            Ok(Datarate::LrFhss { coding_rate: LrFhssCodingRate::_2_3, bandwidth: 1_523_000, .. })
+        ));
+        assert!(!MacEU868::validate_data_rate::<DeviceSpecsMock>(8));
+        assert!(MacEU868::validate_data_rate::<LrFhssDeviceSpecsMock>(11));
+        assert!(!MacEU868::validate_data_rate::<LrFhssDeviceSpecsMock>(12));
+        assert!(!MacUS915::validate_data_rate::<DeviceSpecsMock>(5));
+        assert!(MacUS915::validate_data_rate::<LrFhssDeviceSpecsMock>(6));
+        // 3 headers and 570 bits of coded 20 byte PHYPayload, CRC and tail
+        assert_eq!(EU868::time_on_air_us(DR::_8, 20).unwrap(), 1_867_776);
+        // 2 headers and 285 bits
+        assert_eq!(US915::time_on_air_us(DR::_6, 20).unwrap(), 1_050_624);
+        assert_eq!(EU868::get_rx1_dr(DR::_9, 1).unwrap(), DR::_1);
+        assert_eq!(US915::get_rx1_dr(DR::_5, 0).unwrap(), DR::_10);
+        assert_eq!(US915::max_frm_payload_size(DR::_5, false).unwrap(), 50);
     }
 
     #[test]
@@ -1026,7 +2197,578 @@ pub(crate) mod tests {
         assert!(channels_us915[7].is_some());
         assert!(channels_us915[8].is_some());
         assert!(channels_us915[9].is_none());
+
+        // the default channels of the other dynamic channel plan regions fit in the first channel block
+        let mac_in865 = Mac::<IN865, DynamicChannelPlan<IN865>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let channels_in865 =
+            mac_in865.channel_plan.get_send_channels(&mut rng, Frame::Join, None).unwrap();
+        assert!(channels_in865[0].is_some());
+        assert!(channels_in865[1..].iter().all(Option::is_none));
+        let mac_ru864 = Mac::<RU864, DynamicChannelPlan<RU864>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let channels_ru864 =
+            mac_ru864.channel_plan.get_send_channels(&mut rng, Frame::Join, None).unwrap();
+        assert!([868_900_000, 869_100_000].contains(&channels_ru864[0].unwrap().ul_frequency));
+        assert!(channels_ru864[1..].iter().all(Option::is_none));
     }
+    #[test]
+    fn us915_join_sweep() {
+        let mut mac_us915 = Mac::<US915, FixedChannelPlan<US915>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let mut rng = RngMock;
+        let mut join_channels = [None; 16];
+        for join_channel in join_channels.iter_mut() {
+            let channels = mac_us915.channel_plan.get_join_channels(&mut rng, Some(2)).unwrap();
+            assert!(channels[1..].iter().all(Option::is_none));
+            *join_channel = channels[0];
+        }
+        for (attempt, channel) in join_channels.iter().enumerate() {
+            let sub_band = (2 + attempt / 2) % 8;
+            let channel = channel.unwrap();
+            let dr = US915::override_ul_data_rate_if_necessary(
+                DR::_0,
+                Frame::Join,
+                channel.get_ul_data_rate_range(),
+            );
+            if attempt % 2 == 0 {
+                let first = 902_300_000 + 1_600_000 * sub_band as u32;
+                assert!((first..first + 1_600_000).contains(&channel.get_ul_frequency()));
+                assert_eq!(dr, DR::_0);
+            } else {
+                assert_eq!(channel.get_ul_frequency(), 903_000_000 + 1_600_000 * sub_band as u32);
+                assert_eq!(dr, DR::_4);
+            }
+        }
+
+        // uplinks are restricted to the sub-band of the join accept, which starts the next sweep
+        mac_us915.channel_plan.handle_join_accepted(&join_channels[9].unwrap());
+        assert_eq!(mac_us915.channel_plan.variant(), Some(6));
+        let mask = mac_us915.channel_plan.get_channel_mask();
+        assert!((0..72).all(|index| mask[index] == ((48..56).contains(&index) || index == 70)));
+        for _ in 0..10 {
+            let channels =
+                mac_us915.channel_plan.get_send_channels(&mut rng, Frame::Data, None).unwrap();
+            assert!(channels.iter().flatten().count() == 2);
+        }
+        let channels = mac_us915.channel_plan.get_join_channels(&mut rng, None).unwrap();
+        assert!((911_900_000..913_500_000).contains(&channels[0].unwrap().get_ul_frequency()));
+
+        let mut restored = FixedChannelPlan::<US915>::default();
+        restored.set_variant(6).unwrap();
+        assert_eq!(restored.get_channel_mask(), mask);
+        assert!(restored.set_variant(8).is_err());
+
+        // a channel mask set by the network server is restored with the session, while the sub-band of the join
+        // still starts the next sweep
+        let mut mask = [false; MAX_CHANNELS];
+        mask[8..16].fill(true);
+        mask[65] = true;
+        mac_us915.session = Some(Session::new(
+            NwkSKey::from([1u8; 16]),
+            AppSKey::from([1u8; 16]),
+            DevAddr::from([1u8; 4]),
+        ));
+        mac_us915.channel_plan.set_channel_mask(mask).unwrap();
+        mac_us915.update_session_channel_plan();
+        let session = mac_us915.session.take().unwrap();
+        assert_eq!(session.channel_plan_variant, Some(6));
+        assert_eq!(session.channel_mask, Some([0, 0xFF, 0, 0, 0, 0, 0, 0, 0x02]));
+
+        let mut mac_restored = Mac::<US915, FixedChannelPlan<US915>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        mac_restored.restore_session(session);
+        assert_eq!(mac_restored.channel_plan.get_channel_mask(), mask);
+        assert_eq!(mac_restored.channel_plan.variant(), Some(6));
+        let channels = mac_restored.channel_plan.get_join_channels(&mut rng, None).unwrap();
+        assert!((911_900_000..913_500_000).contains(&channels[0].unwrap().get_ul_frequency()));
+    }
+
+    // the first 64 channels use 125 KHz and the last 8 use 500 KHz
+    fn fixed_channel_data_rates<R: Region>(join_data_rates: (DR, DR)) {
+        let mut rng = RngMock;
+        let mut channel_plan = FixedChannelPlan::<R>::default();
+        for index in 0..72 {
+            let mut mask = [false; MAX_CHANNELS];
+            mask[index] = true;
+            channel_plan.set_channel_mask(mask).unwrap();
+            let channels = channel_plan.get_send_channels(&mut rng, Frame::Data, None).unwrap();
+            let channel = channels.iter().flatten().next().unwrap();
+            assert_eq!(channel.get_ul_frequency(), R::mandatory_frequency(index, true));
+            let (join_data_rate, bandwidth) = if index < 64 {
+                (join_data_rates.0, Bandwidth::_125KHz)
+            } else {
+                (join_data_rates.1, Bandwidth::_500KHz)
+            };
+            let range = channel.get_ul_data_rate_range();
+            assert_eq!(
+                R::override_ul_data_rate_if_necessary(DR::_3, Frame::Join, range),
+                join_data_rate
+            );
+            assert!(join_data_rate.in_range(range));
+            for dr in [DR::_0, DR::_3, DR::_4, DR::_6, DR::_8, DR::_15] {
+                assert_eq!(R::override_ul_data_rate_if_necessary(dr, Frame::Data, range), dr);
+                if !dr.in_range(range) {
+                    continue;
+                }
+                if let Datarate::LoRa { bandwidth: data_rate_bandwidth, .. } =
+                    R::convert_data_rate(dr).unwrap()
+                {
+                    assert_eq!(data_rate_bandwidth, bandwidth);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_channels() {
+        fixed_channel_data_rates::<US915>((DR::_0, DR::_4));
+        fixed_channel_data_rates::<AU915>((DR::_2, DR::_6));
+        // data frames skip the channels whose range excludes the data rate, rather than changing it
+        let mut mac_us915 = Mac::<US915, FixedChannelPlan<US915>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        mac_us915.configuration.tx_data_rate = Some(DR::_5);
+        let chn_125_khz = mac_us915.channel_plan.get_channel(0).unwrap();
+        let chn_500_khz = mac_us915.channel_plan.get_channel(64).unwrap();
+        assert_eq!(
+            mac_us915.channel_data_rate(&chn_125_khz, Frame::Data, 1_000),
+            Err(NoValidChannelReason::DataRate(DR::_5))
+        );
+        assert_eq!(mac_us915.channel_data_rate(&chn_500_khz, Frame::Data, 1_000), Ok(DR::_5));
+        assert_eq!(mac_us915.channel_data_rate(&chn_125_khz, Frame::Join, 1_000), Ok(DR::_0));
+    }
+
+    #[test]
+    fn channel_mask_usable() {
+        let mac_us915 = Mac::<US915, FixedChannelPlan<US915>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let mut mask = [false; MAX_CHANNELS];
+        assert!(!mac_us915.is_channel_mask_usable(&mask, DR::_0));
+        mask[72] = true;
+        assert!(!mac_us915.is_channel_mask_usable(&mask, DR::_4));
+        mask[71] = true;
+        assert!(mac_us915.is_channel_mask_usable(&mask, DR::_4));
+        // no 125 KHz channel is enabled
+        assert!(!mac_us915.is_channel_mask_usable(&mask, DR::_3));
+        mask[0] = true;
+        assert!(mac_us915.is_channel_mask_usable(&mask, DR::_3));
+        assert!(!mac_us915.is_channel_mask_usable(&mask, DR::_8));
+
+        let mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let mut mask = [false; MAX_CHANNELS];
+        mask[3] = true;
+        assert!(!mac_eu868.is_channel_mask_usable(&mask, DR::_0));
+        mask[2] = true;
+        assert!(mac_eu868.is_channel_mask_usable(&mask, DR::_5));
+        assert!(!mac_eu868.is_channel_mask_usable(&mask, DR::_6));
+    }
+
+    #[test]
+    fn channel_fallback() {
+        let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        // a CFList channel at 867.1 MHz, in sub-band g rather than g1 of the default channels
+        let zero = [0u8; 3];
+        let frequencies = [[0x18, 0x4f, 0x84], zero, zero, zero, zero];
+        let cf_list =
+            CfList::DynamicChannel(frequencies.each_ref().map(|f| Frequency::new(f).unwrap()));
+        mac_eu868.channel_plan.handle_cf_list(cf_list).unwrap();
+        let chn = mac_eu868.channel_plan.get_channel(0).unwrap();
+        let others = mac_eu868.channel_plan.get_other_channels_in_block(&chn, 1);
+        let frequencies = others.map(|other| other.map(|other| other.get_ul_frequency()));
+        assert_eq!(
+            frequencies,
+            [Some(868_300_000), Some(868_500_000), Some(867_100_000), None, None, None, None, None]
+        );
+
+        // the default channels are in time-off, but the CFList channel is not
+        mac_eu868.duty_cycle.record_tx(EU868::sub_bands(), 0.0, 868_100_000, 1_000, 100);
+        let mut sensing = ChannelSensingMock { busy_frequency: 0, sensed: std::vec::Vec::new() };
+        let mut unavailable = None;
+        let (selected, data_rate) = block_on(mac_eu868.select_channel(
+            &mut sensing,
+            chn,
+            Frame::Data,
+            2_000,
+            0,
+            &mut unavailable,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(selected.get_ul_frequency(), 867_100_000);
+        assert_eq!(data_rate, DR::_0);
+        assert_eq!(unavailable, Some(NoValidChannelReason::DutyCycle(8_900)));
+
+        mac_eu868.duty_cycle.record_tx(EU868::sub_bands(), 0.0, 867_100_000, 1_500, 100);
+        let mut unavailable = None;
+        assert!(block_on(mac_eu868.select_channel(
+            &mut sensing,
+            chn,
+            Frame::Data,
+            2_000,
+            0,
+            &mut unavailable
+        ))
+        .unwrap()
+        .is_none());
+        assert_eq!(unavailable, Some(NoValidChannelReason::DutyCycle(8_900)));
+        // EU868 does not require Listen Before Talk
+        assert!(sensing.sensed.is_empty());
+    }
+
+    #[test]
+    fn channel_busy_fallback() {
+        let mut mac_kr920 = Mac::<KR920, DynamicChannelPlan<KR920>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let chn = mac_kr920.channel_plan.get_channel(0).unwrap();
+        assert_eq!(chn.get_ul_frequency(), 922_100_000);
+        let mut sensing =
+            ChannelSensingMock { busy_frequency: 922_100_000, sensed: std::vec::Vec::new() };
+        let mut unavailable = None;
+        let (selected, data_rate) = block_on(mac_kr920.select_channel(
+            &mut sensing,
+            chn,
+            Frame::Data,
+            1_000,
+            0,
+            &mut unavailable,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(selected.get_ul_frequency(), 922_300_000);
+        assert_eq!(data_rate, DR::_0);
+        assert_eq!(unavailable, Some(NoValidChannelReason::ChannelsBusy));
+        assert_eq!(sensing.sensed, [922_100_000, 922_300_000]);
+
+        // a clear channel is selected without sensing the others
+        let chn = mac_kr920.channel_plan.get_channel(2).unwrap();
+        let mut sensing =
+            ChannelSensingMock { busy_frequency: 922_100_000, sensed: std::vec::Vec::new() };
+        let mut unavailable = None;
+        let (selected, _) = block_on(mac_kr920.select_channel(
+            &mut sensing,
+            chn,
+            Frame::Data,
+            1_000,
+            0,
+            &mut unavailable,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(selected.get_ul_frequency(), 922_500_000);
+        assert_eq!(unavailable, None);
+        assert_eq!(sensing.sensed, [922_500_000]);
+    }
+
+    #[test]
+    fn channel_unavailable() {
+        let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        let chn = mac_eu868.channel_plan.get_channel(0).unwrap();
+        assert_eq!(mac_eu868.channel_data_rate(&chn, Frame::Data, 1_000), Ok(DR::_0));
+        mac_eu868.configuration.tx_data_rate = Some(DR::_6);
+        assert_eq!(
+            mac_eu868.channel_data_rate(&chn, Frame::Data, 1_000),
+            Err(NoValidChannelReason::DataRate(DR::_6))
+        );
+        mac_eu868.configuration.tx_data_rate = Some(DR::_5);
+        mac_eu868.duty_cycle.record_tx(
+            EU868::sub_bands(),
+            mac_eu868.configuration.max_duty_cycle,
+            chn.get_ul_frequency(),
+            2_000,
+            100,
+        );
+        assert_eq!(
+            mac_eu868.channel_data_rate(&chn, Frame::Data, 3_000),
+            Err(NoValidChannelReason::DutyCycle(8_900))
+        );
+        assert_eq!(mac_eu868.channel_data_rate(&chn, Frame::Data, 11_900), Ok(DR::_5));
+        mac_eu868.configuration.join_back_off.record_tx(12_000, 400);
+        assert_eq!(
+            mac_eu868.channel_data_rate(&chn, Frame::Join, 12_000),
+            Err(NoValidChannelReason::DutyCycle(39_600))
+        );
+        assert_eq!(mac_eu868.channel_data_rate(&chn, Frame::Data, 12_000), Ok(DR::_5));
+
+        // the reason most likely to clear soonest is reported
+        let reason = NoValidChannelReason::DataRate(DR::_6).combine(None);
+        assert_eq!(reason, NoValidChannelReason::DataRate(DR::_6));
+        let reason = NoValidChannelReason::DutyCycle(5_000).combine(Some(reason));
+        let reason = NoValidChannelReason::DutyCycle(2_000).combine(Some(reason));
+        let reason = NoValidChannelReason::DutyCycle(3_000).combine(Some(reason));
+        assert_eq!(reason, NoValidChannelReason::DutyCycle(2_000));
+        assert_eq!(
+            NoValidChannelReason::ChannelMask.combine(Some(reason)),
+            NoValidChannelReason::DutyCycle(2_000)
+        );
+        assert_eq!(
+            NoValidChannelReason::ChannelsBusy.combine(Some(reason)),
+            NoValidChannelReason::ChannelsBusy
+        );
+    }
+
+    #[test]
+    fn dynamic_channel_plan_regions() {
+        assert_eq!(IN865::default_rx2_frequency(), 866_550_000);
+        assert_eq!(IN865::default_rx2_data_rate(), DR::_2);
+        assert_eq!(IN865::get_rx1_dr(DR::_3, 7).unwrap(), DR::_5);
+        assert_eq!(IN865::max_eirp(), 30);
+        assert_eq!(RU864::default_rx2_frequency(), 869_100_000);
+        assert_eq!(RU864::max_eirp(), 16);
+        assert_eq!(EU433::mandatory_frequency(2, true), 433_575_000);
+        assert_eq!(EU433::modify_dbm(5, None, EU433::max_eirp()).unwrap(), Some(2));
+        assert!(EU433::modify_dbm(6, None, EU433::max_eirp()).is_err());
+        assert_eq!(CN779::default_rx2_frequency(), 786_000_000);
+        assert_eq!(CN779::max_frm_payload_size(DR::_0, false).unwrap(), 51);
+        assert!(matches!(CN779::convert_data_rate(DR::_7), Ok(Datarate::Fsk { bit_rate: 50_000 })));
+        assert_eq!(RU864::sub_bands(), [duty_cycle::SubBand::new(864_000_000, 870_000_000, 100)]);
+    }
+
+    #[test]
+    fn dynamic_channel_plan_region_data_rates() {
+        // DR6 is RFU in IN865
+        assert!(IN865::convert_data_rate(DR::_5).is_ok());
+        assert!(IN865::convert_data_rate(DR::_6).is_err());
+        assert!(IN865::max_mac_payload_size(DR::_6, false).is_err());
+        assert!(IN865::get_rx1_dr(DR::_6, 0).is_err());
+        for convert_data_rate in
+            [RU864::convert_data_rate, EU433::convert_data_rate, CN779::convert_data_rate]
+        {
+            assert!(matches!(
+                convert_data_rate(DR::_6),
+                Ok(Datarate::LoRa {
+                    spreading_factor: SpreadingFactor::_7,
+                    bandwidth: Bandwidth::_250KHz
+                })
+            ));
+            assert!(matches!(convert_data_rate(DR::_7), Ok(Datarate::Fsk { bit_rate: 50_000 })));
+            assert!(convert_data_rate(DR::_8).is_err());
+        }
+
+        let max_mac_payload_sizes = [59, 59, 59, 123, 250, 250, 250, 250];
+        for (index, max_mac_payload_size) in max_mac_payload_sizes.into_iter().enumerate() {
+            let dr = DR::try_from(index as u8).unwrap();
+            if index < 6 {
+                assert_eq!(IN865::max_mac_payload_size(dr, false).unwrap(), max_mac_payload_size);
+            }
+            assert_eq!(RU864::max_mac_payload_size(dr, false).unwrap(), max_mac_payload_size);
+            assert_eq!(EU433::max_mac_payload_size(dr, false).unwrap(), max_mac_payload_size);
+            assert_eq!(CN779::max_mac_payload_size(dr, false).unwrap(), max_mac_payload_size);
+        }
+        assert!(RU864::max_mac_payload_size(DR::_8, false).is_err());
+        assert!(EU433::max_mac_payload_size(DR::_8, false).is_err());
+        assert!(CN779::max_mac_payload_size(DR::_8, false).is_err());
+
+        // RX1 data rates by uplink data rate and RX1 data rate offset
+        let in865_rx1_drs = [
+            [DR::_0, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0, DR::_1, DR::_2],
+            [DR::_1, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0, DR::_2, DR::_3],
+            [DR::_2, DR::_1, DR::_0, DR::_0, DR::_0, DR::_0, DR::_3, DR::_4],
+            [DR::_3, DR::_2, DR::_1, DR::_0, DR::_0, DR::_0, DR::_4, DR::_5],
+            [DR::_4, DR::_3, DR::_2, DR::_1, DR::_0, DR::_0, DR::_5, DR::_5],
+            [DR::_5, DR::_4, DR::_3, DR::_2, DR::_1, DR::_0, DR::_5, DR::_5],
+        ];
+        for (ul_dr, rx1_drs) in in865_rx1_drs.iter().enumerate() {
+            let ul_dr = DR::try_from(ul_dr as u8).unwrap();
+            for (rx1_dr_offset, rx1_dr) in rx1_drs.iter().enumerate() {
+                assert_eq!(IN865::get_rx1_dr(ul_dr, rx1_dr_offset as u8).unwrap(), *rx1_dr);
+            }
+            assert!(IN865::get_rx1_dr(ul_dr, 8).is_err());
+        }
+        let rx1_drs = [
+            [DR::_0, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_1, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_2, DR::_1, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_3, DR::_2, DR::_1, DR::_0, DR::_0, DR::_0],
+            [DR::_4, DR::_3, DR::_2, DR::_1, DR::_0, DR::_0],
+            [DR::_5, DR::_4, DR::_3, DR::_2, DR::_1, DR::_0],
+            [DR::_6, DR::_5, DR::_4, DR::_3, DR::_2, DR::_1],
+            [DR::_7, DR::_6, DR::_5, DR::_4, DR::_3, DR::_2],
+        ];
+        for get_rx1_dr in [RU864::get_rx1_dr, EU433::get_rx1_dr, CN779::get_rx1_dr] {
+            for (ul_dr, rx1_drs) in rx1_drs.iter().enumerate() {
+                let ul_dr = DR::try_from(ul_dr as u8).unwrap();
+                for (rx1_dr_offset, rx1_dr) in rx1_drs.iter().enumerate() {
+                    assert_eq!(get_rx1_dr(ul_dr, rx1_dr_offset as u8).unwrap(), *rx1_dr);
+                }
+                assert!(get_rx1_dr(ul_dr, 6).is_err());
+            }
+            assert!(get_rx1_dr(DR::_8, 0).is_err());
+        }
+    }
+    #[test]
+    fn time_on_air() {
+        let within_1ms =
+            |time_on_air_us: u32, expected_us: u32| time_on_air_us.abs_diff(expected_us) <= 1000;
+        // SF12BW125, using the low data rate optimisation
+        assert!(within_1ms(EU868::time_on_air_us(DR::_0, 20).unwrap(), 1_318_912));
+        // SF7BW125
+        assert!(within_1ms(EU868::time_on_air_us(DR::_5, 20).unwrap(), 56_576));
+        // SF8BW500
+        assert!(within_1ms(US915::time_on_air_us(DR::_4, 20).unwrap(), 25_728));
+        assert!(EU868::time_on_air_us(DR::_15, 20).is_err());
+    }
+    #[test]
+    fn max_payload_size() {
+        let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        assert_eq!(mac_eu868.max_payload_size().unwrap(), 51);
+        mac_eu868.configuration.tx_data_rate = Some(DR::_5);
+        assert_eq!(mac_eu868.max_payload_size().unwrap(), 242);
+        mac_eu868
+            .uplink_cmds
+            .push(UplinkMacCommandCreator::LinkADRAns(LinkADRAnsCreator::new()))
+            .unwrap();
+        assert_eq!(mac_eu868.max_payload_size().unwrap(), 240);
+
+        let mut mac_us915 = Mac::<US915, FixedChannelPlan<US915>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        assert_eq!(mac_us915.max_payload_size().unwrap(), 11);
+        mac_us915.configuration.tx_data_rate = Some(DR::_7);
+        assert!(mac_us915.max_payload_size().is_err());
+    }
+    #[test]
+    fn class_c_rx_windows() {
+        let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        mac_eu868.class = Class::C;
+        let chn = mac_eu868.channel_plan.get_channel(0).unwrap();
+        let mut receiver = WindowReceptionMock { opened: std::vec::Vec::new() };
+        let mut buf = [0u8; 256];
+
+        // an unconfirmed uplink without downlink completes once RX2 closes
+        let rx =
+            block_on(mac_eu868.rx_with_timeout(Frame::Data, &mut receiver, &mut buf, DR::_5, &chn));
+        assert!(matches!(rx, Ok(None)));
+        assert_eq!(
+            receiver.opened,
+            [(Window::_1, 868_100_000, 985), (Window::_2, 869_525_000, 1_985)]
+        );
+    }
+    #[test]
+    fn payload_size_exceeded() {
+        let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        assert!(matches!(
+            mac_eu868.start_uplink::<DeviceSpecsMock>(0, true),
+            Err(Error::NetworkNotJoined)
+        ));
+        mac_eu868.session = Some(Session {
+            nwkskey: NwkSKey::from([1u8; 16]),
+            appskey: AppSKey::from([1u8; 16]),
+            devaddr: DevAddr::from([1u8; 4]),
+            fcnt_up: 5,
+            fcnt_down: 0,
+            // the ADR back-off is due
+            adr_ack_cnt: 96,
+            channel_plan_variant: None,
+            channel_mask: None,
+        });
+        // 100 bytes fit DR3 but not DR2, to which the back-off lowers the data rate
+        mac_eu868.configuration.tx_data_rate = Some(DR::_3);
+        assert_eq!(mac_eu868.max_payload_size().unwrap(), 115);
+        assert!(matches!(
+            mac_eu868.start_uplink::<DeviceSpecsMock>(100, true),
+            Err(Error::PayloadSizeExceeded)
+        ));
+        let session = mac_eu868.session.as_ref().unwrap();
+        assert_eq!(session.fcnt_up, 5);
+        assert_eq!(session.adr_ack_cnt, 96);
+        assert_eq!(mac_eu868.configuration.tx_data_rate, Some(DR::_3));
+
+        mac_eu868.start_uplink::<DeviceSpecsMock>(51, true).unwrap();
+        let session = mac_eu868.session.as_ref().unwrap();
+        assert_eq!(session.fcnt_up, 6);
+        assert_eq!(session.adr_ack_cnt, 96);
+        assert_eq!(mac_eu868.configuration.tx_data_rate, Some(DR::_2));
+
+        // without ADR there is no back-off
+        mac_eu868.configuration.tx_data_rate = Some(DR::_3);
+        mac_eu868.start_uplink::<DeviceSpecsMock>(100, false).unwrap();
+        assert_eq!(mac_eu868.configuration.tx_data_rate, Some(DR::_3));
+    }
+    #[test]
+    fn uplink_requests() {
+        type MacEU868 = Mac<EU868, DynamicChannelPlan<EU868>>;
+        let mut mac_eu868 =
+            MacEU868::new(Default::default(), Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]));
+        mac_eu868.request_link_check().unwrap();
+        mac_eu868.request_device_time().unwrap();
+        mac_eu868.request_link_check().unwrap();
+        assert_eq!(mac_eu868.uplink_cmds.len(), 2);
+        assert_eq!(mac_eu868.pending_fopts_len(), 2);
+    }
+
+    #[test]
+    fn tx_param_setup() {
+        let mut mac_as923 = Mac::<AS923_1, DynamicChannelPlan<AS923_1>>::new(
+            Default::default(),
+            Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]),
+        );
+        // dwell time limits apply until the network server lifts them
+        assert_eq!(mac_as923.max_payload_size().unwrap(), 11);
+        mac_as923.configuration.rx1_data_rate_offset = Some(2);
+        assert_eq!(mac_as923.rx1_data_rate(DR::_2), DR::_2);
+        mac_as923.configuration.uplink_dwell_time = Some(false);
+        mac_as923.configuration.downlink_dwell_time = Some(false);
+        assert_eq!(mac_as923.max_payload_size().unwrap(), 115);
+        assert_eq!(mac_as923.rx1_data_rate(DR::_2), DR::_0);
+
+        mac_as923.configuration.tx_power = Some(14);
+        assert_eq!(
+            Mac::<AS923_1, DynamicChannelPlan<AS923_1>>::get_tx_pwr::<DeviceSpecsMock>(
+                Frame::Data,
+                &mac_as923.configuration
+            ),
+            14
+        );
+        mac_as923.configuration.max_eirp = Some(10);
+        assert_eq!(
+            Mac::<AS923_1, DynamicChannelPlan<AS923_1>>::get_tx_pwr::<DeviceSpecsMock>(
+                Frame::Join,
+                &mac_as923.configuration
+            ),
+            10
+        );
+        assert_eq!(
+            Mac::<AS923_1, DynamicChannelPlan<AS923_1>>::get_tx_pwr::<DeviceSpecsMock>(
+                Frame::Data,
+                &mac_as923.configuration
+            ),
+            10
+        );
+    }
+
     #[test]
     fn prepare_buffer() {
         let mut mac_eu868 = Mac::<EU868, DynamicChannelPlan<EU868>>::new(
@@ -1040,6 +2782,8 @@ pub(crate) mod tests {
             fcnt_up: 0,
             fcnt_down: 0,
             adr_ack_cnt: 0,
+            channel_plan_variant: None,
+            channel_mask: None,
         });
         let mut ans = LinkADRAnsCreator::new();
         ans.set_tx_power_ack(true);
@@ -0,0 +1,243 @@
+//! Region selected at runtime, for example during commissioning, rather than through a type parameter.  Use
+//! [`AnyRegion`] with [`AnyChannelPlan`](super::channel_plan::any::AnyChannelPlan) as the `Mac` type parameters.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use lora_modulation::CodingRate;
+
+use super::as923::{AS923_1, AS923_2, AS923_3, AS923_4};
+use super::au915::AU915;
+use super::channel_plan::dynamic::DynamicChannel;
+use super::cn470::CN470;
+use super::cn779::CN779;
+use super::eu433::EU433;
+use super::eu868::EU868;
+use super::in865::IN865;
+use super::kr920::KR920;
+use super::ru864::RU864;
+use super::us915::US915;
+use super::{Error, ListenBeforeTalk, Region};
+use crate::device::types::Datarate;
+use crate::mac::duty_cycle::SubBand;
+use crate::mac::types::{Frame, DR};
+
+const NOT_SELECTED: u8 = u8::MAX;
+static SELECTED: AtomicU8 = AtomicU8::new(NOT_SELECTED);
+
+/// Identification of a region which may be selected at runtime.  The CN470 region is identified together with
+/// the antenna type of the end device, which determines its channel plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs, non_camel_case_types)]
+#[repr(u8)]
+pub enum RegionId {
+    EU868 = 0,
+    US915 = 1,
+    AU915 = 2,
+    AS923_1 = 3,
+    AS923_2 = 4,
+    AS923_3 = 5,
+    AS923_4 = 6,
+    CN470_20M = 7,
+    CN470_26M = 8,
+    KR920 = 9,
+    IN865 = 10,
+    RU864 = 11,
+    EU433 = 12,
+    CN779 = 13,
+}
+
+impl TryFrom<u8> for RegionId {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RegionId::EU868),
+            1 => Ok(RegionId::US915),
+            2 => Ok(RegionId::AU915),
+            3 => Ok(RegionId::AS923_1),
+            4 => Ok(RegionId::AS923_2),
+            5 => Ok(RegionId::AS923_3),
+            6 => Ok(RegionId::AS923_4),
+            7 => Ok(RegionId::CN470_20M),
+            8 => Ok(RegionId::CN470_26M),
+            9 => Ok(RegionId::KR920),
+            10 => Ok(RegionId::IN865),
+            11 => Ok(RegionId::RU864),
+            12 => Ok(RegionId::EU433),
+            13 => Ok(RegionId::CN779),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Processing for the region selected through [`AnyRegion::select`].  A `Mac` using `AnyRegion` returns
+/// [`Error::RegionNotSelected`](crate::mac::Error::RegionNotSelected) until a region is selected.  The selection is
+/// persisted by [`Device::persist_to_non_volatile`](crate::device::Device::persist_to_non_volatile) for a `Mac`
+/// using `AnyRegion`, and given by [`Configuration::region`](crate::mac::types::Configuration::region) once restored.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AnyRegion;
+
+impl AnyRegion {
+    /// Select the region.  The channel plan of a `Mac` is created for the region selected at the time, so select
+    /// the region before creating the `Mac`, and create a new one after selecting another region.
+    pub fn select(id: RegionId) {
+        SELECTED.store(id as u8, Ordering::Relaxed);
+    }
+
+    /// Get the selected region, if any.
+    pub fn selected() -> Option<RegionId> {
+        RegionId::try_from(SELECTED.load(Ordering::Relaxed)).ok()
+    }
+
+    /// Get the region to dispatch to.  Until a region is selected the `Mac` refuses to operate, through
+    /// `Mac::check_region`, so the channel plan and parameters of EU868 are only placeholders.
+    pub(crate) fn id() -> RegionId {
+        Self::selected().unwrap_or(RegionId::EU868)
+    }
+
+    /// Clear the selection, for tests holding [`SELECTION`].
+    #[cfg(test)]
+    pub(crate) fn deselect() {
+        SELECTED.store(NOT_SELECTED, Ordering::Relaxed);
+    }
+}
+
+/// Serializes the tests which select a region, as the selection is shared between the tests run in parallel.
+#[cfg(test)]
+pub(crate) static SELECTION: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+macro_rules! dispatch {
+    ($function:ident($($arg:expr),*)) => {
+        match AnyRegion::id() {
+            RegionId::EU868 => EU868::$function($($arg),*),
+            RegionId::US915 => US915::$function($($arg),*),
+            RegionId::AU915 => AU915::$function($($arg),*),
+            RegionId::AS923_1 => AS923_1::$function($($arg),*),
+            RegionId::AS923_2 => AS923_2::$function($($arg),*),
+            RegionId::AS923_3 => AS923_3::$function($($arg),*),
+            RegionId::AS923_4 => AS923_4::$function($($arg),*),
+            RegionId::CN470_20M | RegionId::CN470_26M => CN470::$function($($arg),*),
+            RegionId::KR920 => KR920::$function($($arg),*),
+            RegionId::IN865 => IN865::$function($($arg),*),
+            RegionId::RU864 => RU864::$function($($arg),*),
+            RegionId::EU433 => EU433::$function($($arg),*),
+            RegionId::CN779 => CN779::$function($($arg),*),
+        }
+    };
+}
+
+impl Region for AnyRegion {
+    fn default_channels(is_uplink: bool) -> usize {
+        dispatch!(default_channels(is_uplink))
+    }
+    fn channel_from_list(channel_id: usize) -> Result<DynamicChannel, Error> {
+        dispatch!(channel_from_list(channel_id))
+    }
+    fn mandatory_frequency(index: usize, is_uplink: bool) -> u32 {
+        dispatch!(mandatory_frequency(index, is_uplink))
+    }
+    fn mandatory_ul_data_rate_range(index: usize) -> (DR, DR) {
+        dispatch!(mandatory_ul_data_rate_range(index))
+    }
+    fn ul_data_rate_range() -> (DR, DR) {
+        dispatch!(ul_data_rate_range())
+    }
+    fn max_ul_data_rate() -> DR {
+        dispatch!(max_ul_data_rate())
+    }
+    fn default_data_rate() -> DR {
+        dispatch!(default_data_rate())
+    }
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        frame: Frame,
+        ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        dispatch!(override_ul_data_rate_if_necessary(dr, frame, ul_data_rate_range))
+    }
+    fn default_coding_rate() -> CodingRate {
+        dispatch!(default_coding_rate())
+    }
+    fn default_rx2_frequency() -> u32 {
+        dispatch!(default_rx2_frequency())
+    }
+    fn default_rx2_data_rate() -> DR {
+        dispatch!(default_rx2_data_rate())
+    }
+    fn max_eirp() -> i8 {
+        dispatch!(max_eirp())
+    }
+    fn min_frequency() -> u32 {
+        dispatch!(min_frequency())
+    }
+    fn max_frequency() -> u32 {
+        dispatch!(max_frequency())
+    }
+    fn convert_data_rate(dr: DR) -> Result<Datarate, Error> {
+        dispatch!(convert_data_rate(dr))
+    }
+    fn next_adr_data_rate(current_dr: Option<DR>) -> Option<DR> {
+        dispatch!(next_adr_data_rate(current_dr))
+    }
+    fn get_rx1_dr(ul_dr: DR, rx1_dr_offset: u8) -> Result<DR, Error> {
+        dispatch!(get_rx1_dr(ul_dr, rx1_dr_offset))
+    }
+    fn supports_tx_param_setup() -> bool {
+        dispatch!(supports_tx_param_setup())
+    }
+    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
+        dispatch!(modify_dbm(tx_power, cur_dbm, max_eirp))
+    }
+    fn default_rx_delay() -> u16 {
+        dispatch!(default_rx_delay())
+    }
+    fn default_rx1_data_rate_offset() -> u8 {
+        dispatch!(default_rx1_data_rate_offset())
+    }
+    fn default_join_accept_delay1() -> u16 {
+        dispatch!(default_join_accept_delay1())
+    }
+    fn default_join_accept_delay2() -> u16 {
+        dispatch!(default_join_accept_delay2())
+    }
+    fn default_adr_ack_limit() -> u8 {
+        dispatch!(default_adr_ack_limit())
+    }
+    fn default_adr_ack_delay() -> u8 {
+        dispatch!(default_adr_ack_delay())
+    }
+    fn time_on_air_us(dr: DR, len: u8) -> Result<u32, Error> {
+        dispatch!(time_on_air_us(dr, len))
+    }
+    fn default_dwell_time(is_uplink: bool) -> bool {
+        dispatch!(default_dwell_time(is_uplink))
+    }
+    fn min_rx1_data_rate(dwell_time: bool) -> DR {
+        dispatch!(min_rx1_data_rate(dwell_time))
+    }
+    fn max_mac_payload_size(dr: DR, dwell_time: bool) -> Result<usize, Error> {
+        dispatch!(max_mac_payload_size(dr, dwell_time))
+    }
+    fn max_frm_payload_size(dr: DR, dwell_time: bool) -> Result<usize, Error> {
+        dispatch!(max_frm_payload_size(dr, dwell_time))
+    }
+    fn lbt() -> Option<ListenBeforeTalk> {
+        dispatch!(lbt())
+    }
+    fn sub_bands() -> &'static [SubBand] {
+        dispatch!(sub_bands())
+    }
+    fn beacon_frequency(channel: u8) -> u32 {
+        dispatch!(beacon_frequency(channel))
+    }
+    fn beacon_data_rate() -> DR {
+        dispatch!(beacon_data_rate())
+    }
+    fn beacon_channels() -> u8 {
+        dispatch!(beacon_channels())
+    }
+    fn beacon_rfu_lengths() -> (usize, usize) {
+        dispatch!(beacon_rfu_lengths())
+    }
+}
@@ -0,0 +1,246 @@
+//! Processing for the AS923 region family, which uses a dynamic channel plan.  The AS923-1 through AS923-4
+//! variants share one channel layout, shifted by a variant-specific frequency offset.
+
+use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
+
+use super::channel_plan::dynamic::{DynamicChannel, DynamicChannelPlan};
+use super::Error;
+use crate::device::types::Datarate;
+use crate::mac::types::{Frame, DR};
+
+/// Specific processing for the AS923 region variant given by `VARIANT` (1 through 4).
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AS923<const VARIANT: u8>;
+
+/// AS923-1, used in Japan among others.
+#[allow(non_camel_case_types)]
+pub type AS923_1 = AS923<1>;
+/// AS923-2, with channels 1.8 MHz below AS923-1.
+#[allow(non_camel_case_types)]
+pub type AS923_2 = AS923<2>;
+/// AS923-3, with channels 6.6 MHz below AS923-1.
+#[allow(non_camel_case_types)]
+pub type AS923_3 = AS923<3>;
+/// AS923-4, with channels 5.9 MHz below AS923-1.
+#[allow(non_camel_case_types)]
+pub type AS923_4 = AS923<4>;
+
+impl<const VARIANT: u8> AS923<VARIANT> {
+    /// Offset applied to the AS923-1 channel frequencies.
+    const OFFSET: i32 = match VARIANT {
+        2 => -1_800_000,
+        3 => -6_600_000,
+        4 => -5_900_000,
+        _ => 0,
+    };
+
+    const fn frequency(as923_1_frequency: u32) -> u32 {
+        (as923_1_frequency as i32 + Self::OFFSET) as u32
+    }
+}
+
+impl<const VARIANT: u8> crate::mac::Region for AS923<VARIANT> {
+    fn default_channels(_is_uplink: bool) -> usize {
+        2
+    }
+    fn channel_from_list(channel_id: usize) -> Result<DynamicChannel, Error> {
+        DynamicChannelPlan::<Self>::get_900_channel(channel_id)
+    }
+    fn mandatory_frequency(index: usize, _is_uplink: bool) -> u32 {
+        Self::frequency(923_200_000 + (200_000 * index as u32))
+    }
+    fn mandatory_ul_data_rate_range(_index: usize) -> (DR, DR) {
+        (DR::_0, DR::_5)
+    }
+    fn ul_data_rate_range() -> (DR, DR) {
+        (DR::_0, DR::_5)
+    }
+    fn min_frequency() -> u32 {
+        match VARIANT {
+            2 => 920_000_000,
+            4 => 917_000_000,
+            _ => 915_000_000,
+        }
+    }
+    fn max_frequency() -> u32 {
+        match VARIANT {
+            2 => 923_000_000,
+            3 => 921_000_000,
+            4 => 920_000_000,
+            _ => 928_000_000,
+        }
+    }
+    fn default_rx2_frequency() -> u32 {
+        Self::frequency(923_200_000)
+    }
+    fn default_rx2_data_rate() -> DR {
+        DR::_2
+    }
+    fn default_data_rate() -> DR {
+        // DR0 and DR1 cannot carry a frame within the 400 ms dwell time limit
+        DR::_2
+    }
+    fn default_dwell_time(_is_uplink: bool) -> bool {
+        true
+    }
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        _frame: Frame,
+        _ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        if dr.in_range(Self::ul_data_rate_range()) {
+            dr
+        } else {
+            Self::default_data_rate()
+        }
+    }
+
+    fn default_rx1_data_rate_offset() -> u8 {
+        0
+    }
+
+    fn convert_data_rate(dr: DR) -> Result<Datarate, super::Error> {
+        match dr {
+            DR::_0 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_12,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_1 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_11,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_2 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_10,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_3 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_9,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_4 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_8,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_5 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_6 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_250KHz,
+            }),
+            _ => Err(super::Error::DataRateNotSupported(dr)),
+        }
+    }
+
+    fn next_adr_data_rate(current_dr: Option<DR>) -> Option<DR> {
+        match current_dr {
+            Some(DR::_2) => None,
+            Some(DR::_3) => Some(DR::_2),
+            Some(DR::_4) => Some(DR::_3),
+            Some(DR::_5) => Some(DR::_4),
+            Some(DR::_6) => Some(DR::_5),
+            _ => Some(DR::_2),
+        }
+    }
+
+    fn default_coding_rate() -> CodingRate {
+        CodingRate::_4_5
+    }
+    fn max_eirp() -> i8 {
+        16
+    }
+    fn supports_tx_param_setup() -> bool {
+        true
+    }
+    fn min_rx1_data_rate(dwell_time: bool) -> DR {
+        if dwell_time {
+            DR::_2
+        } else {
+            DR::_0
+        }
+    }
+    fn max_mac_payload_size(dr: DR, dwell_time: bool) -> Result<usize, Error> {
+        match (dr, dwell_time) {
+            (DR::_0 | DR::_1, false) => Ok(59),
+            (DR::_2 | DR::_3, false) => Ok(123),
+            (DR::_2, true) => Ok(19),
+            (DR::_3, true) => Ok(61),
+            (DR::_4, true) => Ok(133),
+            (DR::_4 | DR::_5 | DR::_6 | DR::_7, _) => Ok(250),
+            _ => Err(Error::DataRateNotSupported(dr)),
+        }
+    }
+    fn beacon_frequency(_channel: u8) -> u32 {
+        Self::frequency(923_400_000)
+    }
+    fn beacon_data_rate() -> DR {
+        DR::_3
+    }
+
+    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
+        match tx_power {
+            0..=7 => {
+                let next_dbm = max_eirp.checked_sub_unsigned(tx_power * 2);
+                if next_dbm.is_none() {
+                    Err(Error::InvalidTxPower)
+                } else {
+                    Ok(next_dbm)
+                }
+            }
+            15 => Ok(cur_dbm),
+            _ => Err(Error::InvalidTxPower),
+        }
+    }
+
+    fn get_rx1_dr(ul_dr: DR, rx1_dr_offset: u8) -> Result<DR, super::Error> {
+        // offsets 6 and 7 raise the downlink data rate by 1 and 2, capped at DR5
+        const EFFECTIVE_OFFSETS: [i8; 8] = [0, 1, 2, 3, 4, 5, -1, -2];
+        if rx1_dr_offset > 7 || !ul_dr.in_range((DR::_0, DR::_7)) {
+            return Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset));
+        }
+        let dr = (ul_dr as i8 - EFFECTIVE_OFFSETS[rx1_dr_offset as usize]).clamp(0, 5);
+        DR::try_from(dr as u8)
+            .map_err(|_| super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mac::Region;
+
+    #[test]
+    fn frequency_offsets() {
+        assert_eq!(AS923_1::mandatory_frequency(0, true), 923_200_000);
+        assert_eq!(AS923_2::mandatory_frequency(1, true), 921_600_000);
+        assert_eq!(AS923_3::default_rx2_frequency(), 916_600_000);
+        assert_eq!(AS923_4::beacon_frequency(0), 917_500_000);
+        for frequency in
+            [AS923_4::mandatory_frequency(0, true), AS923_4::mandatory_frequency(1, true)]
+        {
+            assert!((AS923_4::min_frequency()..=AS923_4::max_frequency()).contains(&frequency));
+        }
+    }
+
+    #[test]
+    fn dwell_time_payload_sizes() {
+        assert_eq!(AS923_1::max_mac_payload_size(DR::_0, false).unwrap(), 59);
+        assert!(AS923_1::max_mac_payload_size(DR::_1, true).is_err());
+        assert_eq!(AS923_1::max_frm_payload_size(DR::_2, true).unwrap(), 11);
+        assert_eq!(AS923_1::max_frm_payload_size(DR::_2, false).unwrap(), 115);
+        assert_eq!(AS923_1::max_mac_payload_size(DR::_4, true).unwrap(), 133);
+        assert_eq!(AS923_1::max_mac_payload_size(DR::_5, true).unwrap(), 250);
+    }
+
+    #[test]
+    fn rx1_data_rate() {
+        assert_eq!(AS923_1::get_rx1_dr(DR::_5, 0).unwrap(), DR::_5);
+        assert_eq!(AS923_1::get_rx1_dr(DR::_5, 2).unwrap(), DR::_3);
+        assert_eq!(AS923_1::get_rx1_dr(DR::_2, 2).unwrap(), DR::_0);
+        assert_eq!(AS923_1::min_rx1_data_rate(true), DR::_2);
+        assert_eq!(AS923_1::get_rx1_dr(DR::_3, 6).unwrap(), DR::_4);
+        assert_eq!(AS923_1::get_rx1_dr(DR::_5, 7).unwrap(), DR::_5);
+        assert!(AS923_1::get_rx1_dr(DR::_5, 8).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Processing for the AU915 region, which uses a fixed channel plan.
+
+use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
+
+use super::channel_plan::dynamic::DynamicChannel;
+use super::{fixed_plan, Error};
+use crate::device::types::Datarate;
+use crate::mac::types::{Frame, DR};
+
+/// Specific processing for the AU915 region.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AU915;
+
+impl crate::mac::Region for AU915 {
+    fn default_channels(is_uplink: bool) -> usize {
+        fixed_plan::default_channels(is_uplink)
+    }
+
+    fn channel_from_list(_channel_id: usize) -> Result<DynamicChannel, Error> {
+        Err(Error::UnsupportedChannelListForRegion)
+    }
+
+    fn mandatory_frequency(index: usize, is_uplink: bool) -> u32 {
+        if is_uplink {
+            // upstream: 64 (915.2 to 927.8 [+ by 0.2]) + 8 (915.9 to 927.1 [+ by 1.6])
+            if index < 64 {
+                (915_200_000 + (200_000 * index)) as u32
+            } else {
+                (915_900_000 + (1_600_000 * (index - 64))) as u32
+            }
+        } else {
+            fixed_plan::downlink_frequency(index)
+        }
+    }
+
+    fn mandatory_ul_data_rate_range(index: usize) -> (DR, DR) {
+        // 64 125 KHz channels using DR0 through DR5 + 8 500 KHz channels using DR6
+        if index < 64 {
+            (DR::_0, DR::_5)
+        } else {
+            (DR::_6, DR::_6)
+        }
+    }
+
+    fn ul_data_rate_range() -> (DR, DR) {
+        (DR::_0, DR::_6)
+    }
+
+    fn default_data_rate() -> DR {
+        // DR0 and DR1 cannot carry a frame within the 400 ms uplink dwell time limit
+        DR::_2
+    }
+
+    fn default_dwell_time(is_uplink: bool) -> bool {
+        is_uplink
+    }
+
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        frame: Frame,
+        ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        // join requests use DR2 on the 125 KHz channels and DR6 on the 500 KHz channels
+        fixed_plan::override_ul_data_rate(dr, frame, ul_data_rate_range, Self::default_data_rate())
+    }
+
+    fn default_coding_rate() -> CodingRate {
+        CodingRate::_4_5
+    }
+
+    fn default_rx2_frequency() -> u32 {
+        fixed_plan::downlink_frequency(0)
+    }
+
+    fn default_rx2_data_rate() -> DR {
+        DR::_8
+    }
+
+    fn max_eirp() -> i8 {
+        30
+    }
+
+    fn min_frequency() -> u32 {
+        915000000
+    }
+
+    fn max_frequency() -> u32 {
+        928000000
+    }
+
+    fn convert_data_rate(dr: DR) -> Result<Datarate, super::Error> {
+        match dr {
+            DR::_0 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_12,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_1 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_11,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_2 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_10,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_3 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_9,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_4 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_8,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_5 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_6 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_8,
+                bandwidth: Bandwidth::_500KHz,
+            }),
+            _ => fixed_plan::convert_downlink_data_rate(dr),
+        }
+    }
+
+    fn next_adr_data_rate(current_dr: Option<DR>) -> Option<DR> {
+        match current_dr {
+            Some(DR::_0) => None,
+            Some(DR::_1) => Some(DR::_0),
+            Some(DR::_2) => Some(DR::_1),
+            Some(DR::_3) => Some(DR::_2),
+            Some(DR::_4) => Some(DR::_3),
+            Some(DR::_5) => Some(DR::_4),
+            Some(DR::_6) => Some(DR::_5),
+            _ => Some(DR::_0),
+        }
+    }
+
+    fn get_rx1_dr(ul_dr: DR, rx1_dr_offset: u8) -> Result<DR, super::Error> {
+        if rx1_dr_offset > 5 {
+            return Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset));
+        }
+        let dl_dr_matrix = [
+            [DR::_8, DR::_8, DR::_8, DR::_8, DR::_8, DR::_8],
+            [DR::_9, DR::_8, DR::_8, DR::_8, DR::_8, DR::_8],
+            [DR::_10, DR::_9, DR::_8, DR::_8, DR::_8, DR::_8],
+            [DR::_11, DR::_10, DR::_9, DR::_8, DR::_8, DR::_8],
+            [DR::_12, DR::_11, DR::_10, DR::_9, DR::_8, DR::_8],
+            [DR::_13, DR::_12, DR::_11, DR::_10, DR::_9, DR::_8],
+            [DR::_13, DR::_13, DR::_12, DR::_11, DR::_10, DR::_9],
+        ];
+        match ul_dr {
+            DR::_0 => Ok(dl_dr_matrix[0][rx1_dr_offset as usize]),
+            DR::_1 => Ok(dl_dr_matrix[1][rx1_dr_offset as usize]),
+            DR::_2 => Ok(dl_dr_matrix[2][rx1_dr_offset as usize]),
+            DR::_3 => Ok(dl_dr_matrix[3][rx1_dr_offset as usize]),
+            DR::_4 => Ok(dl_dr_matrix[4][rx1_dr_offset as usize]),
+            DR::_5 => Ok(dl_dr_matrix[5][rx1_dr_offset as usize]),
+            DR::_6 => Ok(dl_dr_matrix[6][rx1_dr_offset as usize]),
+            _ => Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset)),
+        }
+    }
+
+    fn supports_tx_param_setup() -> bool {
+        true
+    }
+
+    fn max_mac_payload_size(dr: DR, dwell_time: bool) -> Result<usize, Error> {
+        match (dr, dwell_time) {
+            (DR::_0 | DR::_1 | DR::_2, false) => Ok(59),
+            (DR::_3, false) => Ok(123),
+            (DR::_2, true) => Ok(19),
+            (DR::_3, true) => Ok(61),
+            (DR::_4, true) => Ok(133),
+            (DR::_4 | DR::_5 | DR::_6, _) => Ok(250),
+            (DR::_8, _) => Ok(61),
+            (DR::_9, _) => Ok(137),
+            (DR::_10 | DR::_11 | DR::_12 | DR::_13, _) => Ok(250),
+            _ => Err(Error::DataRateNotSupported(dr)),
+        }
+    }
+
+    fn beacon_frequency(channel: u8) -> u32 {
+        // the same as the downstream channels
+        fixed_plan::downlink_frequency(channel as usize)
+    }
+
+    fn beacon_data_rate() -> DR {
+        DR::_8
+    }
+
+    fn beacon_channels() -> u8 {
+        8
+    }
+
+    fn beacon_rfu_lengths() -> (usize, usize) {
+        (5, 3)
+    }
+
+    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
+        fixed_plan::modify_dbm(tx_power, cur_dbm, max_eirp)
+    }
+
+    fn default_rx1_data_rate_offset() -> u8 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mac::Region;
+
+    #[test]
+    fn channels() {
+        assert_eq!(AU915::mandatory_frequency(0, true), 915_200_000);
+        assert_eq!(AU915::mandatory_frequency(63, true), 927_800_000);
+        assert_eq!(AU915::mandatory_frequency(64, true), 915_900_000);
+        assert_eq!(AU915::mandatory_frequency(71, true), 927_100_000);
+        assert_eq!(AU915::mandatory_frequency(7, false), 927_500_000);
+        assert_eq!(
+            AU915::override_ul_data_rate_if_necessary(DR::_3, Frame::Data, (DR::_6, DR::_6)),
+            DR::_3
+        );
+        assert_eq!(
+            AU915::override_ul_data_rate_if_necessary(DR::_5, Frame::Join, (DR::_0, DR::_5)),
+            DR::_2
+        );
+        assert_eq!(
+            AU915::override_ul_data_rate_if_necessary(DR::_5, Frame::Data, (DR::_0, DR::_5)),
+            DR::_5
+        );
+    }
+
+    #[test]
+    fn data_rates() {
+        assert_eq!(AU915::get_rx1_dr(DR::_6, 0).unwrap(), DR::_13);
+        assert_eq!(AU915::get_rx1_dr(DR::_2, 1).unwrap(), DR::_9);
+        assert_eq!(AU915::get_rx1_dr(DR::_5, 5).unwrap(), DR::_8);
+        assert!(AU915::get_rx1_dr(DR::_8, 0).is_err());
+        assert_eq!(AU915::max_mac_payload_size(DR::_2, false).unwrap(), 59);
+        assert_eq!(AU915::max_mac_payload_size(DR::_2, true).unwrap(), 19);
+        assert!(AU915::max_mac_payload_size(DR::_1, true).is_err());
+        assert_eq!(AU915::max_mac_payload_size(DR::_9, true).unwrap(), 137);
+        assert!(AU915::default_dwell_time(true));
+        assert!(!AU915::default_dwell_time(false));
+    }
+}
@@ -0,0 +1,381 @@
+//! Channel plan processing for the region selected at runtime through [`AnyRegion`].
+
+use encoding::maccommands::{ChannelMask, DlChannelReqPayload, NewChannelReqPayload};
+use encoding::parser::CfList;
+
+use super::cn470::{CN470Channel, CN470ChannelPlan20M, CN470ChannelPlan26M};
+use super::dynamic::{DynamicChannel, DynamicChannelPlan};
+use super::fixed::{FixedChannel, FixedChannelPlan};
+use super::{Channel, ChannelPlan, Error, MAX_CHANNELS, NUM_OF_CHANNEL_BLOCKS};
+use crate::device::rng::Rng;
+use crate::mac::region::any::{AnyRegion, RegionId};
+use crate::mac::region::as923::{AS923_1, AS923_2, AS923_3, AS923_4};
+use crate::mac::region::au915::AU915;
+use crate::mac::region::cn779::CN779;
+use crate::mac::region::eu433::EU433;
+use crate::mac::region::eu868::EU868;
+use crate::mac::region::in865::IN865;
+use crate::mac::region::kr920::KR920;
+use crate::mac::region::ru864::RU864;
+use crate::mac::region::us915::US915;
+use crate::mac::types::*;
+
+/// A channel of the channel plan used by the selected region.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum AnyChannel {
+    Dynamic(DynamicChannel),
+    Fixed(FixedChannel),
+    CN470(CN470Channel),
+}
+
+impl Channel for AnyChannel {
+    fn get_ul_frequency(&self) -> u32 {
+        match self {
+            AnyChannel::Dynamic(channel) => channel.get_ul_frequency(),
+            AnyChannel::Fixed(channel) => channel.get_ul_frequency(),
+            AnyChannel::CN470(channel) => channel.get_ul_frequency(),
+        }
+    }
+
+    fn get_dl_frequency(&self) -> u32 {
+        match self {
+            AnyChannel::Dynamic(channel) => channel.get_dl_frequency(),
+            AnyChannel::Fixed(channel) => channel.get_dl_frequency(),
+            AnyChannel::CN470(channel) => channel.get_dl_frequency(),
+        }
+    }
+
+    fn get_ul_data_rate_range(&self) -> (DR, DR) {
+        match self {
+            AnyChannel::Dynamic(channel) => channel.get_ul_data_rate_range(),
+            AnyChannel::Fixed(channel) => channel.get_ul_data_rate_range(),
+            AnyChannel::CN470(channel) => channel.get_ul_data_rate_range(),
+        }
+    }
+}
+
+impl From<DynamicChannel> for AnyChannel {
+    fn from(channel: DynamicChannel) -> Self {
+        AnyChannel::Dynamic(channel)
+    }
+}
+
+impl From<FixedChannel> for AnyChannel {
+    fn from(channel: FixedChannel) -> Self {
+        AnyChannel::Fixed(channel)
+    }
+}
+
+impl From<CN470Channel> for AnyChannel {
+    fn from(channel: CN470Channel) -> Self {
+        AnyChannel::CN470(channel)
+    }
+}
+
+/// Access to the concrete channel held by an [`AnyChannel`].
+trait FromAnyChannel: Sized {
+    fn from_any(channel: &AnyChannel) -> Option<&Self>;
+}
+
+impl FromAnyChannel for DynamicChannel {
+    fn from_any(channel: &AnyChannel) -> Option<&Self> {
+        match channel {
+            AnyChannel::Dynamic(channel) => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+impl FromAnyChannel for FixedChannel {
+    fn from_any(channel: &AnyChannel) -> Option<&Self> {
+        match channel {
+            AnyChannel::Fixed(channel) => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+impl FromAnyChannel for CN470Channel {
+    fn from_any(channel: &AnyChannel) -> Option<&Self> {
+        match channel {
+            AnyChannel::CN470(channel) => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+/// The channel plan of the region selected through [`AnyRegion::select`] when the channel plan is created.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs, non_camel_case_types)]
+pub enum AnyChannelPlan {
+    EU868(DynamicChannelPlan<EU868>),
+    US915(FixedChannelPlan<US915>),
+    AU915(FixedChannelPlan<AU915>),
+    AS923_1(DynamicChannelPlan<AS923_1>),
+    AS923_2(DynamicChannelPlan<AS923_2>),
+    AS923_3(DynamicChannelPlan<AS923_3>),
+    AS923_4(DynamicChannelPlan<AS923_4>),
+    CN470_20M(CN470ChannelPlan20M),
+    CN470_26M(CN470ChannelPlan26M),
+    KR920(DynamicChannelPlan<KR920>),
+    IN865(DynamicChannelPlan<IN865>),
+    RU864(DynamicChannelPlan<RU864>),
+    EU433(DynamicChannelPlan<EU433>),
+    CN779(DynamicChannelPlan<CN779>),
+}
+
+impl AnyChannelPlan {
+    /// Get the region the channel plan was created for.
+    pub fn region(&self) -> RegionId {
+        match self {
+            AnyChannelPlan::EU868(_) => RegionId::EU868,
+            AnyChannelPlan::US915(_) => RegionId::US915,
+            AnyChannelPlan::AU915(_) => RegionId::AU915,
+            AnyChannelPlan::AS923_1(_) => RegionId::AS923_1,
+            AnyChannelPlan::AS923_2(_) => RegionId::AS923_2,
+            AnyChannelPlan::AS923_3(_) => RegionId::AS923_3,
+            AnyChannelPlan::AS923_4(_) => RegionId::AS923_4,
+            AnyChannelPlan::CN470_20M(_) => RegionId::CN470_20M,
+            AnyChannelPlan::CN470_26M(_) => RegionId::CN470_26M,
+            AnyChannelPlan::KR920(_) => RegionId::KR920,
+            AnyChannelPlan::IN865(_) => RegionId::IN865,
+            AnyChannelPlan::RU864(_) => RegionId::RU864,
+            AnyChannelPlan::EU433(_) => RegionId::EU433,
+            AnyChannelPlan::CN779(_) => RegionId::CN779,
+        }
+    }
+}
+
+impl Default for AnyChannelPlan {
+    fn default() -> Self {
+        match AnyRegion::id() {
+            RegionId::EU868 => AnyChannelPlan::EU868(Default::default()),
+            RegionId::US915 => AnyChannelPlan::US915(Default::default()),
+            RegionId::AU915 => AnyChannelPlan::AU915(Default::default()),
+            RegionId::AS923_1 => AnyChannelPlan::AS923_1(Default::default()),
+            RegionId::AS923_2 => AnyChannelPlan::AS923_2(Default::default()),
+            RegionId::AS923_3 => AnyChannelPlan::AS923_3(Default::default()),
+            RegionId::AS923_4 => AnyChannelPlan::AS923_4(Default::default()),
+            RegionId::CN470_20M => AnyChannelPlan::CN470_20M(Default::default()),
+            RegionId::CN470_26M => AnyChannelPlan::CN470_26M(Default::default()),
+            RegionId::KR920 => AnyChannelPlan::KR920(Default::default()),
+            RegionId::IN865 => AnyChannelPlan::IN865(Default::default()),
+            RegionId::RU864 => AnyChannelPlan::RU864(Default::default()),
+            RegionId::EU433 => AnyChannelPlan::EU433(Default::default()),
+            RegionId::CN779 => AnyChannelPlan::CN779(Default::default()),
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:expr, $plan:ident => $body:expr) => {
+        match $self {
+            AnyChannelPlan::EU868($plan) => $body,
+            AnyChannelPlan::US915($plan) => $body,
+            AnyChannelPlan::AU915($plan) => $body,
+            AnyChannelPlan::AS923_1($plan) => $body,
+            AnyChannelPlan::AS923_2($plan) => $body,
+            AnyChannelPlan::AS923_3($plan) => $body,
+            AnyChannelPlan::AS923_4($plan) => $body,
+            AnyChannelPlan::CN470_20M($plan) => $body,
+            AnyChannelPlan::CN470_26M($plan) => $body,
+            AnyChannelPlan::KR920($plan) => $body,
+            AnyChannelPlan::IN865($plan) => $body,
+            AnyChannelPlan::RU864($plan) => $body,
+            AnyChannelPlan::EU433($plan) => $body,
+            AnyChannelPlan::CN779($plan) => $body,
+        }
+    };
+}
+
+impl ChannelPlan<AnyRegion> for AnyChannelPlan {
+    type Channel = AnyChannel;
+
+    fn get_random_channels_from_blocks(
+        &self,
+        channel_block_randoms: [u32; NUM_OF_CHANNEL_BLOCKS],
+    ) -> Result<[Option<Self::Channel>; NUM_OF_CHANNEL_BLOCKS], Error> {
+        dispatch!(self, plan => plan
+            .get_random_channels_from_blocks(channel_block_randoms)
+            .map(|channels| channels.map(|channel| channel.map(AnyChannel::from))))
+    }
+
+    fn handle_new_channel_req(&mut self, payload: NewChannelReqPayload) -> Result<(), Error> {
+        dispatch!(self, plan => plan.handle_new_channel_req(payload))
+    }
+
+    fn check_uplink_frequency_exists(&self, index: usize) -> bool {
+        dispatch!(self, plan => plan.check_uplink_frequency_exists(index))
+    }
+
+    fn get_channel(&self, index: usize) -> Option<AnyChannel> {
+        dispatch!(self, plan => plan.get_channel(index).map(AnyChannel::from))
+    }
+
+    fn handle_channel_mask(
+        &mut self,
+        new_mask: &mut [bool; MAX_CHANNELS],
+        channel_mask: ChannelMask<2>,
+        channel_mask_ctrl: u8,
+    ) -> Result<(), Error> {
+        dispatch!(self, plan => plan.handle_channel_mask(new_mask, channel_mask, channel_mask_ctrl))
+    }
+
+    fn get_channel_mask(&self) -> [bool; MAX_CHANNELS] {
+        dispatch!(self, plan => plan.get_channel_mask())
+    }
+
+    fn set_channel_mask(&mut self, mask: [bool; MAX_CHANNELS]) -> Result<(), Error> {
+        dispatch!(self, plan => plan.set_channel_mask(mask))
+    }
+
+    fn handle_dl_channel_req(&mut self, payload: DlChannelReqPayload) -> Result<(), Error> {
+        dispatch!(self, plan => plan.handle_dl_channel_req(payload))
+    }
+
+    fn handle_cf_list(&mut self, cf_list: CfList) -> Result<(), Error> {
+        dispatch!(self, plan => plan.handle_cf_list(cf_list))
+    }
+
+    fn validate_frequency(&self, frequency: u32) -> Result<(), Error> {
+        dispatch!(self, plan => plan.validate_frequency(frequency))
+    }
+
+    fn reactivate_channels(&mut self) {
+        dispatch!(self, plan => plan.reactivate_channels())
+    }
+
+    fn handle_join_accepted(&mut self, channel: &AnyChannel) {
+        dispatch!(self, plan => {
+            if let Some(channel) = FromAnyChannel::from_any(channel) {
+                plan.handle_join_accepted(channel)
+            }
+        })
+    }
+
+    fn default_rx2_frequency(&self, channel: Option<&AnyChannel>) -> Option<u32> {
+        dispatch!(self, plan => plan.default_rx2_frequency(channel.and_then(FromAnyChannel::from_any)))
+    }
+
+    fn beacon_frequency(&self, channel: u8) -> Option<u32> {
+        dispatch!(self, plan => plan.beacon_frequency(channel))
+    }
+
+    fn get_join_channels<RNG: Rng>(
+        &mut self,
+        rng: &mut RNG,
+        preferred_channel_block: Option<u8>,
+    ) -> Result<[Option<AnyChannel>; NUM_OF_CHANNEL_BLOCKS], RNG::Error> {
+        dispatch!(self, plan => plan
+            .get_join_channels(rng, preferred_channel_block)
+            .map(|channels| channels.map(|channel| channel.map(AnyChannel::from))))
+    }
+
+    fn selected_region(&self) -> Option<RegionId> {
+        Some(self.region())
+    }
+
+    fn variant(&self) -> Option<u8> {
+        dispatch!(self, plan => plan.variant())
+    }
+
+    fn set_variant(&mut self, variant: u8) -> Result<(), Error> {
+        dispatch!(self, plan => plan.set_variant(variant))
+    }
+
+    fn persisted_channel_mask(&self) -> Option<[u8; 9]> {
+        dispatch!(self, plan => plan.persisted_channel_mask())
+    }
+
+    fn restore_channel_mask(&mut self, mask: [u8; 9]) -> Result<(), Error> {
+        dispatch!(self, plan => plan.restore_channel_mask(mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mac::region::any::SELECTION;
+    use crate::mac::region::Region;
+    use crate::mac::{Credentials, Mac};
+
+    fn select(id: Option<RegionId>) -> std::sync::MutexGuard<'static, ()> {
+        let guard = SELECTION.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match id {
+            Some(id) => AnyRegion::select(id),
+            None => AnyRegion::deselect(),
+        }
+        guard
+    }
+
+    #[test]
+    fn region_ids() {
+        for id in 0..=13 {
+            assert_eq!(RegionId::try_from(id).map(|region| region as u8), Ok(id));
+        }
+        assert!(RegionId::try_from(14).is_err());
+    }
+
+    #[test]
+    fn selected_region() {
+        let _selection = select(Some(RegionId::US915));
+        assert_eq!(AnyRegion::selected(), Some(RegionId::US915));
+        assert_eq!(AnyRegion::default_rx2_frequency(), US915::default_rx2_frequency());
+        assert_eq!(AnyRegion::max_eirp(), 30);
+        let channel_plan = AnyChannelPlan::default();
+        assert_eq!(channel_plan.region(), RegionId::US915);
+        let channels =
+            channel_plan.get_random_channels_from_blocks([0; NUM_OF_CHANNEL_BLOCKS]).unwrap();
+        assert!(matches!(channels[0], Some(AnyChannel::Fixed(_))));
+        assert_eq!(channels[0].unwrap().get_ul_frequency(), 902_300_000);
+
+        AnyRegion::select(RegionId::CN470_26M);
+        assert_eq!(AnyRegion::ul_data_rate_range(), (DR::_1, DR::_5));
+        let mut channel_plan = AnyChannelPlan::default();
+        assert_eq!(channel_plan.region(), RegionId::CN470_26M);
+        let channels =
+            channel_plan.get_random_channels_from_blocks([7; NUM_OF_CHANNEL_BLOCKS]).unwrap();
+        assert!(matches!(channels[1], Some(AnyChannel::CN470(_))));
+        channel_plan.handle_join_accepted(&channels[1].unwrap());
+        assert!(channel_plan.variant().is_some());
+        assert_eq!(channel_plan.default_rx2_frequency(None), Some(502_500_000));
+        // a channel of another plan is ignored
+        channel_plan.handle_join_accepted(&AnyChannel::Dynamic(DynamicChannel {
+            ul_frequency: 868_100_000,
+            dl_frequency: 868_100_000,
+            ul_data_rate_range: (DR::_0, DR::_5),
+        }));
+        assert_eq!(channel_plan.default_rx2_frequency(None), Some(502_500_000));
+
+        AnyRegion::select(RegionId::EU868);
+        assert!(matches!(AnyChannelPlan::default(), AnyChannelPlan::EU868(_)));
+        assert_eq!(AnyRegion::sub_bands(), EU868::sub_bands());
+    }
+
+    #[test]
+    fn region_mismatch() {
+        // only a Mac for the selected region keeps the region, and it is not used once another region is selected
+        let _selection = select(Some(RegionId::EU868));
+        let credentials = Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]);
+        let mac = Mac::<AnyRegion, AnyChannelPlan>::new(Default::default(), credentials);
+        assert_eq!(mac.configuration.region(), Some(RegionId::EU868));
+        assert!(mac.check_region().is_ok());
+        AnyRegion::select(RegionId::AU915);
+        assert!(matches!(mac.check_region(), Err(crate::mac::Error::RegionMismatch)));
+        let mac = Mac::<EU868, DynamicChannelPlan<EU868>>::new(Default::default(), credentials);
+        assert_eq!(mac.configuration.region(), None);
+        assert!(mac.check_region().is_ok());
+    }
+
+    #[test]
+    fn region_not_selected() {
+        let _selection = select(None);
+        let credentials = Credentials::new([0u8; 8], [0u8; 8], [0u8; 16]);
+        let mac = Mac::<AnyRegion, AnyChannelPlan>::new(Default::default(), credentials);
+        assert!(matches!(mac.check_region(), Err(crate::mac::Error::RegionNotSelected)));
+        let mac = Mac::<EU868, DynamicChannelPlan<EU868>>::new(Default::default(), credentials);
+        assert!(mac.check_region().is_ok());
+    }
+}
@@ -0,0 +1,367 @@
+//! CN470 channel plan processing, where the channel plan type (20 MHz or 26 MHz antenna, plan A or B) in use
+//! is determined by the channel of the successful join request.
+
+use encoding::maccommands::{ChannelMask, DlChannelReqPayload, NewChannelReqPayload};
+use encoding::parser::CfList;
+
+use super::{
+    Channel, ChannelPlan, Error, MAX_CHANNELS, NUM_OF_CHANNELS_IN_BLOCK, NUM_OF_CHANNEL_BLOCKS,
+};
+use crate::mac::region::cn470::CN470;
+use crate::mac::region::Region;
+use crate::mac::types::*;
+
+/// A CN470 channel plan type, as defined in RP002-1.0.4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum CN470Plan {
+    /// 20 MHz antenna, plan A.
+    A20 = 0,
+    /// 20 MHz antenna, plan B.
+    B20 = 1,
+    /// 26 MHz antenna, plan A.
+    A26 = 2,
+    /// 26 MHz antenna, plan B.
+    B26 = 3,
+}
+
+impl CN470Plan {
+    /// Get the number of uplink channels.
+    pub fn ul_channels(self) -> usize {
+        match self {
+            CN470Plan::A20 | CN470Plan::B20 => 64,
+            CN470Plan::A26 | CN470Plan::B26 => 48,
+        }
+    }
+
+    /// Get the uplink frequency of a channel.
+    pub fn ul_frequency(self, index: usize) -> u32 {
+        let index = index as u32;
+        match self {
+            // 32 (470.3 to 476.5 [+ by 0.2]) + 32 (503.5 to 509.7 [+ by 0.2])
+            CN470Plan::A20 if index < 32 => 470_300_000 + (200_000 * index),
+            CN470Plan::A20 => 503_500_000 + (200_000 * (index - 32)),
+            // 32 (476.9 to 483.1 [+ by 0.2]) + 32 (496.9 to 503.1 [+ by 0.2])
+            CN470Plan::B20 if index < 32 => 476_900_000 + (200_000 * index),
+            CN470Plan::B20 => 496_900_000 + (200_000 * (index - 32)),
+            // 48 (470.3 to 479.7 [+ by 0.2])
+            CN470Plan::A26 => 470_300_000 + (200_000 * index),
+            // 48 (480.3 to 489.7 [+ by 0.2])
+            CN470Plan::B26 => 480_300_000 + (200_000 * index),
+        }
+    }
+
+    /// Get the RX1 downlink frequency for an uplink channel.
+    pub fn dl_frequency(self, index: usize) -> u32 {
+        let index = index as u32;
+        match self {
+            // 32 (483.9 to 490.1 [+ by 0.2]) + 32 (490.3 to 496.5 [+ by 0.2])
+            CN470Plan::A20 => 483_900_000 + (200_000 * index),
+            // the same as the upstream channels
+            CN470Plan::B20 => self.ul_frequency(index as usize),
+            // 24 (490.1 to 494.7 [+ by 0.2]), shared by uplink channels n and n + 24
+            CN470Plan::A26 => 490_100_000 + (200_000 * (index % 24)),
+            // 24 (500.1 to 504.7 [+ by 0.2]), shared by uplink channels n and n + 24
+            CN470Plan::B26 => 500_100_000 + (200_000 * (index % 24)),
+        }
+    }
+
+    /// Get the default RX2 frequency, also used for the Class B beacon and ping slots.
+    pub fn rx2_frequency(self) -> u32 {
+        match self {
+            CN470Plan::A20 => 485_300_000,
+            CN470Plan::B20 => 486_900_000,
+            CN470Plan::A26 => 492_500_000,
+            CN470Plan::B26 => 502_500_000,
+        }
+    }
+
+    fn channel(self, index: usize) -> CN470Channel {
+        CN470Channel {
+            ul_frequency: self.ul_frequency(index),
+            dl_frequency: self.dl_frequency(index),
+            plan: self,
+        }
+    }
+}
+
+impl TryFrom<u8> for CN470Plan {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CN470Plan::A20),
+            1 => Ok(CN470Plan::B20),
+            2 => Ok(CN470Plan::A26),
+            3 => Ok(CN470Plan::B26),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Composition of properties needed to represent a CN470 channel, including the plan type it belongs to.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CN470Channel {
+    pub(crate) ul_frequency: u32,
+    pub(crate) dl_frequency: u32,
+    pub(crate) plan: CN470Plan,
+}
+impl Channel for CN470Channel {
+    fn get_ul_frequency(&self) -> u32 {
+        self.ul_frequency
+    }
+
+    fn get_dl_frequency(&self) -> u32 {
+        self.dl_frequency
+    }
+
+    fn get_ul_data_rate_range(&self) -> (DR, DR) {
+        CN470::ul_data_rate_range()
+    }
+}
+
+/// Composition of properties and functions needed to control the CN470 channel plan for an end device with a
+/// 20 MHz or 26 MHz antenna, given by `ANTENNA_MHZ`.  Join requests alternate between plans A and B for the
+/// antenna; the plan of the channel used for the accepted join request is then used for the session.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CN470ChannelPlan<const ANTENNA_MHZ: u8> {
+    plan: Option<CN470Plan>,
+    mask: [bool; MAX_CHANNELS],
+}
+
+/// CN470 channel plan for an end device with a 20 MHz antenna.
+pub type CN470ChannelPlan20M = CN470ChannelPlan<20>;
+/// CN470 channel plan for an end device with a 26 MHz antenna.
+pub type CN470ChannelPlan26M = CN470ChannelPlan<26>;
+
+impl<const ANTENNA_MHZ: u8> CN470ChannelPlan<ANTENNA_MHZ> {
+    /// Get the plan types available with the antenna.
+    fn plans() -> [CN470Plan; 2] {
+        if ANTENNA_MHZ == 20 {
+            [CN470Plan::A20, CN470Plan::B20]
+        } else {
+            [CN470Plan::A26, CN470Plan::B26]
+        }
+    }
+
+    /// Get the plan type selected through a join, if any.
+    pub fn plan(&self) -> Option<CN470Plan> {
+        self.plan
+    }
+
+    fn select_plan(&mut self, plan: CN470Plan) {
+        self.plan = Some(plan);
+        self.mask = [false; MAX_CHANNELS];
+        self.mask[..plan.ul_channels()].fill(true);
+    }
+
+    fn ul_channels(&self) -> usize {
+        self.plan.map(CN470Plan::ul_channels).unwrap_or_default()
+    }
+}
+
+impl<const ANTENNA_MHZ: u8> Default for CN470ChannelPlan<ANTENNA_MHZ> {
+    fn default() -> Self {
+        Self { plan: None, mask: [false; MAX_CHANNELS] }
+    }
+}
+
+impl<const ANTENNA_MHZ: u8> ChannelPlan<CN470> for CN470ChannelPlan<ANTENNA_MHZ> {
+    type Channel = CN470Channel;
+
+    // Before a join, alternate the channel blocks between plans A and B, choosing any channel of the plan.
+    // Afterwards, randomly choose one valid channel (if one exists) from each channel block of the selected plan.
+    fn get_random_channels_from_blocks(
+        &self,
+        channel_block_randoms: [u32; NUM_OF_CHANNEL_BLOCKS],
+    ) -> Result<[Option<CN470Channel>; NUM_OF_CHANNEL_BLOCKS], crate::mac::region::Error> {
+        let mut random_channels: [Option<CN470Channel>; NUM_OF_CHANNEL_BLOCKS] =
+            [None; NUM_OF_CHANNEL_BLOCKS];
+
+        let Some(plan) = self.plan else {
+            for (i, random_channel) in random_channels.iter_mut().enumerate() {
+                let plan = Self::plans()[i % 2];
+                let index = channel_block_randoms[i] as usize % plan.ul_channels();
+                *random_channel = Some(plan.channel(index));
+            }
+            return Ok(random_channels);
+        };
+
+        for i in 0..NUM_OF_CHANNEL_BLOCKS {
+            let mut count = 0usize;
+            let mut available_channel_ids_in_block: [Option<usize>; NUM_OF_CHANNELS_IN_BLOCK] =
+                [None; NUM_OF_CHANNELS_IN_BLOCK];
+            for j in 0..NUM_OF_CHANNELS_IN_BLOCK {
+                let channel_index: usize = (i * NUM_OF_CHANNELS_IN_BLOCK) + j;
+                if channel_index < plan.ul_channels() && self.mask[channel_index] {
+                    available_channel_ids_in_block[count] = Some(channel_index);
+                    count += 1;
+                }
+            }
+
+            if count > 0 {
+                let random = channel_block_randoms[i] % (count as u32);
+                let channel_id = available_channel_ids_in_block[random as usize].unwrap();
+                random_channels[i] = Some(plan.channel(channel_id));
+            }
+        }
+
+        Ok(random_channels)
+    }
+
+    fn handle_new_channel_req(&mut self, _payload: NewChannelReqPayload) -> Result<(), Error> {
+        Err(Error::CommandNotImplementedForRegion)
+    }
+
+    fn check_uplink_frequency_exists(&self, index: usize) -> bool {
+        index < self.ul_channels()
+    }
+
+    fn get_channel(&self, index: usize) -> Option<CN470Channel> {
+        self.plan.filter(|plan| index < plan.ul_channels()).map(|plan| plan.channel(index))
+    }
+
+    fn handle_channel_mask(
+        &mut self,
+        new_mask: &mut [bool; MAX_CHANNELS],
+        channel_mask: ChannelMask<2>,
+        channel_mask_ctrl: u8,
+    ) -> Result<(), Error> {
+        match channel_mask_ctrl {
+            0..=5 => {
+                let first_index = (channel_mask_ctrl * 16) as usize;
+                for i in 0..16 {
+                    let index = first_index + i;
+                    let is_enabled = channel_mask.is_enabled(i).unwrap();
+                    if index < self.ul_channels() {
+                        new_mask[index] = is_enabled;
+                    } else if is_enabled {
+                        return Err(Error::InvalidChannelIndex);
+                    }
+                }
+                Ok(())
+            }
+            6 => {
+                new_mask[..self.ul_channels()].fill(true);
+                Ok(())
+            }
+            _ => Err(Error::InvalidChannelMaskCtrl),
+        }
+    }
+
+    fn get_channel_mask(&self) -> [bool; MAX_CHANNELS] {
+        self.mask
+    }
+
+    fn set_channel_mask(&mut self, mask: [bool; MAX_CHANNELS]) -> Result<(), Error> {
+        self.mask = mask;
+        Ok(())
+    }
+
+    fn handle_dl_channel_req(&mut self, _payload: DlChannelReqPayload) -> Result<(), Error> {
+        Err(Error::CommandNotImplementedForRegion)
+    }
+
+    fn handle_cf_list(&mut self, cf_list: CfList) -> Result<(), Error> {
+        match cf_list {
+            CfList::FixedChannel(channel_mask) => {
+                for index in 0..self.ul_channels() {
+                    self.mask[index] = channel_mask.is_enabled(index).unwrap_or(false);
+                }
+                Ok(())
+            }
+            CfList::DynamicChannel(_) => Err(Error::InvalidCfListType),
+        }
+    }
+
+    fn validate_frequency(&self, frequency: u32) -> Result<(), Error> {
+        let plans = Self::plans();
+        let plans = match &self.plan {
+            Some(plan) => core::slice::from_ref(plan),
+            None => &plans[..],
+        };
+        for plan in plans {
+            if (0..plan.ul_channels()).any(|index| plan.ul_frequency(index) == frequency) {
+                return Ok(());
+            }
+        }
+        Err(Error::InvalidFrequency)
+    }
+
+    fn reactivate_channels(&mut self) {
+        let ul_channels = self.ul_channels();
+        self.mask[..ul_channels].fill(true);
+    }
+
+    fn handle_join_accepted(&mut self, channel: &CN470Channel) {
+        self.select_plan(channel.plan);
+    }
+
+    fn default_rx2_frequency(&self, channel: Option<&CN470Channel>) -> Option<u32> {
+        self.plan.or(channel.map(|channel| channel.plan)).map(CN470Plan::rx2_frequency)
+    }
+
+    fn beacon_frequency(&self, _channel: u8) -> Option<u32> {
+        self.plan.map(CN470Plan::rx2_frequency)
+    }
+
+    fn variant(&self) -> Option<u8> {
+        self.plan.map(|plan| plan as u8)
+    }
+
+    fn set_variant(&mut self, variant: u8) -> Result<(), Error> {
+        match CN470Plan::try_from(variant) {
+            Ok(plan) if Self::plans().contains(&plan) => {
+                self.select_plan(plan);
+                Ok(())
+            }
+            _ => Err(Error::InvalidChannelPlanVariant),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_selects_plan() {
+        let mut channel_plan = CN470ChannelPlan26M::default();
+        let channels =
+            channel_plan.get_random_channels_from_blocks([7; NUM_OF_CHANNEL_BLOCKS]).unwrap();
+        assert_eq!(channels[0].unwrap().ul_frequency, 471_700_000);
+        assert_eq!(channels[1].unwrap().ul_frequency, 481_700_000);
+        assert_eq!(channels[1].unwrap().dl_frequency, 501_500_000);
+        assert_eq!(channel_plan.default_rx2_frequency(channels[1].as_ref()), Some(502_500_000));
+        assert!(channel_plan.validate_frequency(489_700_000).is_ok());
+
+        channel_plan.handle_join_accepted(&channels[1].unwrap());
+        assert_eq!(channel_plan.plan(), Some(CN470Plan::B26));
+        assert_eq!(channel_plan.default_rx2_frequency(None), Some(502_500_000));
+        assert!(channel_plan.validate_frequency(470_300_000).is_err());
+        let channels =
+            channel_plan.get_random_channels_from_blocks([0; NUM_OF_CHANNEL_BLOCKS]).unwrap();
+        assert_eq!(channels.iter().flatten().count(), 6);
+        assert!(channels.iter().flatten().all(|channel| channel.plan == CN470Plan::B26));
+    }
+
+    #[test]
+    fn plan_frequencies() {
+        assert_eq!(CN470Plan::A20.ul_frequency(63), 509_700_000);
+        assert_eq!(CN470Plan::A20.dl_frequency(32), 490_300_000);
+        assert_eq!(CN470Plan::B20.dl_frequency(40), 498_500_000);
+        assert_eq!(CN470Plan::A26.dl_frequency(47), 494_700_000);
+    }
+
+    #[test]
+    fn variant() {
+        let mut channel_plan = CN470ChannelPlan20M::default();
+        assert_eq!(channel_plan.variant(), None);
+        assert!(channel_plan.set_variant(CN470Plan::A26 as u8).is_err());
+        channel_plan.set_variant(CN470Plan::B20 as u8).unwrap();
+        assert_eq!(channel_plan.variant(), Some(CN470Plan::B20 as u8));
+        assert_eq!(channel_plan.get_channel_mask().iter().filter(|enabled| **enabled).count(), 64);
+    }
+}
@@ -182,6 +182,10 @@ where
         false
     }
 
+    fn get_channel(&self, index: usize) -> Option<DynamicChannel> {
+        self.channels.get(index).copied().flatten()
+    }
+
     fn handle_channel_mask(
         &mut self,
         new_mask: &mut [bool; MAX_CHANNELS],
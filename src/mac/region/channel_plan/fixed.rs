@@ -3,14 +3,19 @@
 use core::marker::PhantomData;
 
 use super::Error;
+use crate::device::rng::Rng;
 use crate::mac::region::Region;
 use crate::mac::types::*;
 use encoding::parser::CfList;
 
 use super::{Channel, ChannelPlan, MAX_CHANNELS, NUM_OF_CHANNELS_IN_BLOCK, NUM_OF_CHANNEL_BLOCKS};
 
+/// Number of sub-bands, each of eight 125 KHz channels and one 500 KHz channel.
+const NUM_OF_SUB_BANDS: usize = 8;
+
 /// Composition of properties and functions needed to represent a fixed channel.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FixedChannel {
     pub(crate) ul_frequency: u32,
     pub(crate) dl_frequency: u32,
@@ -30,16 +35,35 @@ impl Channel for FixedChannel {
     }
 }
 
-/// Composition of properties and functions needed to control a fixed channel plan.
+/// Composition of properties and functions needed to control a fixed channel plan.  Join requests sweep the
+/// sub-bands, alternating between a 125 KHz channel and the 500 KHz channel of each sub-band, so that end devices
+/// find 8-channel gateways.  Uplinks are then restricted to the sub-band of the successful join request until the
+/// network server sets the channel mask, while the sub-band still starts the next join sweep.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FixedChannelPlan<R>
 where
     R: Region,
 {
     channels: [Option<FixedChannel>; MAX_CHANNELS],
     mask: [bool; MAX_CHANNELS],
+    join_attempt: u8,
+    sub_band: Option<u8>,
     region: PhantomData<R>,
 }
 
+impl<R> FixedChannelPlan<R>
+where
+    R: Region,
+{
+    fn select_sub_band(&mut self, sub_band: usize) {
+        self.mask = [false; MAX_CHANNELS];
+        self.mask[sub_band * NUM_OF_CHANNELS_IN_BLOCK..(sub_band + 1) * NUM_OF_CHANNELS_IN_BLOCK]
+            .fill(true);
+        self.mask[NUM_OF_SUB_BANDS * NUM_OF_CHANNELS_IN_BLOCK + sub_band] = true;
+        self.sub_band = Some(sub_band as u8);
+    }
+}
+
 impl<R> Default for FixedChannelPlan<R>
 where
     R: Region,
@@ -55,7 +79,7 @@ where
             });
             mask[index] = true;
         }
-        Self { channels, mask, region: Default::default() }
+        Self { channels, mask, join_attempt: 0, sub_band: None, region: Default::default() }
     }
 }
 
@@ -97,6 +121,27 @@ where
         Ok(random_channels)
     }
 
+    // Start with the sub-band of the last successful join request, or the preferred channel block, and move to
+    // the next sub-band after trying both its 125 KHz and 500 KHz channels.
+    fn get_join_channels<RNG: Rng>(
+        &mut self,
+        rng: &mut RNG,
+        preferred_channel_block: Option<u8>,
+    ) -> Result<[Option<FixedChannel>; NUM_OF_CHANNEL_BLOCKS], RNG::Error> {
+        let first_sub_band = self.sub_band.or(preferred_channel_block).unwrap_or(0) as usize;
+        let sub_band = (first_sub_band + self.join_attempt as usize / 2) % NUM_OF_SUB_BANDS;
+        let channel_index = if self.join_attempt.is_multiple_of(2) {
+            let random = rng.next_u32()? as usize;
+            sub_band * NUM_OF_CHANNELS_IN_BLOCK + random % NUM_OF_CHANNELS_IN_BLOCK
+        } else {
+            NUM_OF_SUB_BANDS * NUM_OF_CHANNELS_IN_BLOCK + sub_band
+        };
+        self.join_attempt = (self.join_attempt + 1) % (2 * NUM_OF_SUB_BANDS as u8);
+        let mut channels = [None; NUM_OF_CHANNEL_BLOCKS];
+        channels[0] = self.channels[channel_index];
+        Ok(channels)
+    }
+
     fn handle_new_channel_req(
         &mut self,
         payload: encoding::maccommands::NewChannelReqPayload,
@@ -123,47 +168,52 @@ where
         false
     }
 
+    fn get_channel(&self, index: usize) -> Option<FixedChannel> {
+        self.channels.get(index).copied().flatten()
+    }
+
+    // Channel mask control as specified for US915 and AU915 in RP002-1.0.4.
     fn handle_channel_mask(
         &mut self,
         new_mask: &mut [bool; MAX_CHANNELS],
         channel_mask: encoding::maccommands::ChannelMask<2>,
         channel_mask_ctrl: u8,
     ) -> Result<(), crate::mac::region::Error> {
+        let bits = u16::from_le_bytes([channel_mask.get_index(0), channel_mask.get_index(1)]);
+        let is_enabled = |bit: usize| bits & (1 << bit) != 0;
+        let first_500_khz_index = NUM_OF_SUB_BANDS * NUM_OF_CHANNELS_IN_BLOCK;
         match channel_mask_ctrl {
+            // 16 of the 125 KHz channels, or the 500 KHz channels for 4
             0..=4 => {
-                for i in 0..15 {
-                    let index = i + (channel_mask_ctrl * 16) as usize;
-                    new_mask[index] = channel_mask.is_enabled(i).unwrap()
+                for bit in 0..16 {
+                    let index = (channel_mask_ctrl as usize * 16) + bit;
+                    if index < R::default_channels(true) {
+                        new_mask[index] = is_enabled(bit);
+                    } else if is_enabled(bit) {
+                        return Err(Error::InvalidChannelIndex);
+                    }
                 }
                 Ok(())
             }
+            // bits 0 through 7 for the sub-bands, each of eight 125 KHz channels and one 500 KHz channel,
+            // with bits 8 through 15 RFU
             5 => {
-                // This algorithm does not implement the modification of single 500 KHz channels
-                // for bits 0 through 7, since processing of bit 8 affects the entire 500 KHz channel block.
-                // Until further evidence, the regional specification for fixed channels is taken to need clarification.
-                let channel_mask_be: u16 =
-                    channel_mask.get_index(0) as u16 | ((channel_mask.get_index(1) as u16) << 8);
-                for i in 0..NUM_OF_CHANNEL_BLOCKS {
-                    let is_channel_block_enabled = channel_mask_be & (1 << i) != 0;
-                    for j in 0..NUM_OF_CHANNELS_IN_BLOCK {
-                        new_mask[(i * NUM_OF_CHANNELS_IN_BLOCK) + j] = is_channel_block_enabled;
-                    }
+                for sub_band in 0..NUM_OF_SUB_BANDS {
+                    let first_index = sub_band * NUM_OF_CHANNELS_IN_BLOCK;
+                    new_mask[first_index..first_index + NUM_OF_CHANNELS_IN_BLOCK]
+                        .fill(is_enabled(sub_band));
+                    new_mask[first_500_khz_index + sub_band] = is_enabled(sub_band);
                 }
                 Ok(())
             }
-            6 => {
-                new_mask.fill(true);
-                for i in 0..15 {
-                    let index = i + 64;
-                    new_mask[index] = channel_mask.is_enabled(i).unwrap()
+            // all 125 KHz channels on for 6 or off for 7, with bits 0 through 7 for the 500 KHz channels
+            6 | 7 => {
+                if bits >> NUM_OF_SUB_BANDS != 0 {
+                    return Err(Error::InvalidChannelIndex);
                 }
-                Ok(())
-            }
-            7 => {
-                new_mask.fill(false);
-                for i in 0..15 {
-                    let index = i + 64;
-                    new_mask[index] = channel_mask.is_enabled(i).unwrap()
+                new_mask[..first_500_khz_index].fill(channel_mask_ctrl == 6);
+                for bit in 0..NUM_OF_SUB_BANDS {
+                    new_mask[first_500_khz_index + bit] = is_enabled(bit);
                 }
                 Ok(())
             }
@@ -179,6 +229,7 @@ where
         &mut self,
         mask: [bool; MAX_CHANNELS],
     ) -> Result<(), crate::mac::region::Error> {
+        // the sub-band of the join is kept to start the next join sweep
         self.mask = mask;
         Ok(())
     }
@@ -217,4 +268,109 @@ where
             self.mask[index] = true;
         }
     }
+
+    fn handle_join_accepted(&mut self, channel: &FixedChannel) {
+        let index = (0..R::default_channels(true)).find(|&index| {
+            self.channels[index]
+                .is_some_and(|candidate| candidate.ul_frequency == channel.ul_frequency)
+        });
+        if let Some(index) = index {
+            let blocks_end = NUM_OF_SUB_BANDS * NUM_OF_CHANNELS_IN_BLOCK;
+            self.select_sub_band(if index < blocks_end {
+                index / NUM_OF_CHANNELS_IN_BLOCK
+            } else {
+                index - blocks_end
+            });
+        }
+        self.join_attempt = 0;
+    }
+
+    fn variant(&self) -> Option<u8> {
+        self.sub_band
+    }
+
+    fn set_variant(&mut self, variant: u8) -> Result<(), Error> {
+        if (variant as usize) < NUM_OF_SUB_BANDS {
+            self.select_sub_band(variant as usize);
+            Ok(())
+        } else {
+            Err(Error::InvalidChannelPlanVariant)
+        }
+    }
+
+    fn persisted_channel_mask(&self) -> Option<[u8; 9]> {
+        let mut mask = [0u8; 9];
+        for index in 0..R::default_channels(true) {
+            if self.mask[index] {
+                mask[index / 8] |= 1 << (index % 8);
+            }
+        }
+        Some(mask)
+    }
+
+    fn restore_channel_mask(&mut self, mask: [u8; 9]) -> Result<(), Error> {
+        let mut new_mask = [false; MAX_CHANNELS];
+        for (index, enabled) in new_mask.iter_mut().enumerate().take(mask.len() * 8) {
+            *enabled = mask[index / 8] & (1 << (index % 8)) != 0;
+            if *enabled && !self.check_uplink_frequency_exists(index) {
+                return Err(Error::InvalidChannelIndex);
+            }
+        }
+        self.mask = new_mask;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding::maccommands::ChannelMask;
+
+    use super::*;
+    use crate::mac::region::au915::AU915;
+    use crate::mac::region::us915::US915;
+
+    fn enabled(mask: &[bool; MAX_CHANNELS]) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_CHANNELS).filter(|index| mask[*index])
+    }
+
+    #[test]
+    fn channel_mask_ctrl() {
+        let mut channel_plan = FixedChannelPlan::<US915>::default();
+        let mut mask = channel_plan.get_channel_mask();
+
+        // sub-band 2 through ChMaskCntl 7 then 3, as sent by network servers for 8-channel gateways
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x02, 0x00]), 7).unwrap();
+        assert!(enabled(&mask).eq([65]));
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x00, 0xFF]), 0).unwrap();
+        assert!(enabled(&mask).eq((8..16).chain([65])));
+
+        // the last bit of each block of 16 channels
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x00, 0x80]), 3).unwrap();
+        assert!(mask[63]);
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x81, 0x00]), 4).unwrap();
+        assert!(enabled(&mask).eq((8..16).chain([63, 64, 71])));
+        assert!(channel_plan
+            .handle_channel_mask(&mut mask, ChannelMask::from([0x00, 0x01]), 4)
+            .is_err());
+
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x81, 0xFF]), 5).unwrap();
+        assert!(enabled(&mask).eq((0..8).chain(56..64).chain([64, 71])));
+
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x00, 0x00]), 6).unwrap();
+        assert!(enabled(&mask).eq(0..64));
+        assert!(channel_plan
+            .handle_channel_mask(&mut mask, ChannelMask::from([0x00, 0x01]), 7)
+            .is_err());
+        assert!(channel_plan
+            .handle_channel_mask(&mut mask, ChannelMask::from([0xFF, 0xFF]), 8)
+            .is_err());
+    }
+
+    #[test]
+    fn au915_channel_mask_ctrl() {
+        let mut channel_plan = FixedChannelPlan::<AU915>::default();
+        let mut mask = channel_plan.get_channel_mask();
+        channel_plan.handle_channel_mask(&mut mask, ChannelMask::from([0x01, 0x00]), 5).unwrap();
+        assert!(enabled(&mask).eq((0..8).chain([64])));
+    }
 }
@@ -4,9 +4,12 @@ use crate::device::rng::Rng;
 use crate::mac::types::*;
 use encoding::maccommands::{ChannelMask, DlChannelReqPayload, NewChannelReqPayload};
 use encoding::parser::CfList;
+pub mod any;
+pub mod cn470;
 pub mod dynamic;
 pub mod fixed;
 
+use super::any::RegionId;
 use super::{Error, Region};
 
 /// Maximum number of channels in a deployed channel plan.
@@ -47,6 +50,8 @@ where
     fn handle_new_channel_req(&mut self, payload: NewChannelReqPayload) -> Result<(), Error>;
     /// Does a channel exist for the given channel ID?
     fn check_uplink_frequency_exists(&self, index: usize) -> bool;
+    /// Get the channel for the given channel ID, if it exists.
+    fn get_channel(&self, index: usize) -> Option<Self::Channel>;
     /// Create a new channel mask collection based on guidance in the input channel_mask_ctrl and input channel_mask,
     /// both contained in a LinkADRReq packet from a network server to the end device.
     fn handle_channel_mask(
@@ -67,6 +72,72 @@ where
     fn validate_frequency(&self, frequency: u32) -> Result<(), Error>;
     /// Reactivate channels for ADR
     fn reactivate_channels(&mut self);
+    /// Handle a JoinAccept received in response to a join request sent on the given channel.
+    fn handle_join_accepted(&mut self, _channel: &Self::Channel) {}
+    /// Get the default RX2 frequency where it depends on the channel plan rather than only on the region,
+    /// optionally given the channel used for the uplink.
+    fn default_rx2_frequency(&self, _channel: Option<&Self::Channel>) -> Option<u32> {
+        None
+    }
+    /// Get the Class B beacon frequency for the given beacon channel where it depends on the channel plan.
+    fn beacon_frequency(&self, _channel: u8) -> Option<u32> {
+        None
+    }
+    /// Get the region the channel plan was created for, for a channel plan of the region selected at runtime through
+    /// [`AnyRegion`](crate::mac::region::any::AnyRegion).
+    fn selected_region(&self) -> Option<RegionId> {
+        None
+    }
+    /// Get the variant selected through a join, for a channel plan with several variants.  It is kept with the
+    /// session, so the variant is restored through [`ChannelPlan::set_variant`] with the session.
+    fn variant(&self) -> Option<u8> {
+        None
+    }
+    /// Select a variant previously given by [`ChannelPlan::variant`].
+    fn set_variant(&mut self, _variant: u8) -> Result<(), Error> {
+        Err(Error::InvalidChannelPlanVariant)
+    }
+    /// Get the uplink channel mask to keep with the session, one bit per channel for up to 72 channels, for a channel
+    /// plan whose mask is not restored with its variant or channel list.
+    fn persisted_channel_mask(&self) -> Option<[u8; 9]> {
+        None
+    }
+    /// Restore a channel mask previously given by [`ChannelPlan::persisted_channel_mask`].
+    fn restore_channel_mask(&mut self, _mask: [u8; 9]) -> Result<(), Error> {
+        Err(Error::InvalidChannelIndex)
+    }
+    /// Get the channels to send the next join request on.  By default, as for [`ChannelPlan::get_send_channels`].
+    fn get_join_channels<RNG: Rng>(
+        &mut self,
+        rng: &mut RNG,
+        preferred_channel_block: Option<u8>,
+    ) -> Result<[Option<<Self as ChannelPlan<R>>::Channel>; NUM_OF_CHANNEL_BLOCKS], RNG::Error>
+    {
+        self.get_send_channels(rng, Frame::Join, preferred_channel_block)
+    }
+    /// Get the enabled channels in the channel block of the given channel, other than the channel itself, to send on
+    /// where the given channel is unavailable.  They start from the position in the block given by `random`.
+    fn get_other_channels_in_block(
+        &self,
+        channel: &Self::Channel,
+        random: u32,
+    ) -> [Option<Self::Channel>; NUM_OF_CHANNELS_IN_BLOCK] {
+        let mask = self.get_channel_mask();
+        let index = (0..MAX_CHANNELS).find(|index| {
+            self.get_channel(*index)
+                .is_some_and(|other| other.get_ul_frequency() == channel.get_ul_frequency())
+        });
+        core::array::from_fn(|position| {
+            let index = index?;
+            let block_start = index - index % NUM_OF_CHANNELS_IN_BLOCK;
+            let other_index = block_start + (position + random as usize) % NUM_OF_CHANNELS_IN_BLOCK;
+            if other_index != index && mask[other_index] {
+                self.get_channel(other_index)
+            } else {
+                None
+            }
+        })
+    }
     /// get channels to send on
     fn get_send_channels<RNG: Rng>(
         &self,
@@ -0,0 +1,196 @@
+//! Processing for the CN470-510 region, which uses the [`CN470ChannelPlan`](super::channel_plan::cn470::CN470ChannelPlan)
+//! for the channel plan type of the end device antenna.
+
+use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
+
+use super::channel_plan::cn470::CN470Plan;
+use super::channel_plan::dynamic::DynamicChannel;
+use super::Error;
+use crate::device::types::Datarate;
+use crate::mac::types::{Frame, DR};
+
+/// Specific processing for the CN470 region.  Frequencies which depend on the channel plan type default to
+/// those of the 20 MHz antenna plan A until a join selects the plan type.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CN470;
+
+impl crate::mac::Region for CN470 {
+    fn default_channels(_is_uplink: bool) -> usize {
+        // every channel of the plan type is enabled after the join, rather than a set of default channels
+        0
+    }
+
+    fn channel_from_list(_channel_id: usize) -> Result<DynamicChannel, Error> {
+        Err(Error::UnsupportedChannelListForRegion)
+    }
+
+    fn mandatory_frequency(index: usize, is_uplink: bool) -> u32 {
+        if is_uplink {
+            CN470Plan::A20.ul_frequency(index)
+        } else {
+            CN470Plan::A20.dl_frequency(index)
+        }
+    }
+
+    fn mandatory_ul_data_rate_range(_index: usize) -> (DR, DR) {
+        (DR::_1, DR::_5)
+    }
+
+    fn ul_data_rate_range() -> (DR, DR) {
+        (DR::_1, DR::_5)
+    }
+
+    fn min_frequency() -> u32 {
+        470000000
+    }
+
+    fn max_frequency() -> u32 {
+        510000000
+    }
+
+    fn default_rx2_frequency() -> u32 {
+        CN470Plan::A20.rx2_frequency()
+    }
+
+    fn default_rx2_data_rate() -> DR {
+        DR::_1
+    }
+
+    fn default_data_rate() -> DR {
+        DR::_1
+    }
+
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        _frame: Frame,
+        _ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        if dr.in_range(CN470::ul_data_rate_range()) {
+            dr
+        } else {
+            CN470::default_data_rate()
+        }
+    }
+
+    fn default_rx1_data_rate_offset() -> u8 {
+        0
+    }
+
+    fn convert_data_rate(dr: DR) -> Result<Datarate, super::Error> {
+        match dr {
+            DR::_0 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_12,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_1 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_11,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_2 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_10,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_3 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_9,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_4 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_8,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_5 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_6 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_500KHz,
+            }),
+            _ => Err(super::Error::DataRateNotSupported(dr)),
+        }
+    }
+
+    fn next_adr_data_rate(current_dr: Option<DR>) -> Option<DR> {
+        match current_dr {
+            Some(DR::_1) => None,
+            Some(DR::_2) => Some(DR::_1),
+            Some(DR::_3) => Some(DR::_2),
+            Some(DR::_4) => Some(DR::_3),
+            Some(DR::_5) => Some(DR::_4),
+            Some(DR::_6) => Some(DR::_5),
+            _ => Some(DR::_1),
+        }
+    }
+
+    fn default_coding_rate() -> CodingRate {
+        CodingRate::_4_5
+    }
+
+    fn max_eirp() -> i8 {
+        19
+    }
+
+    fn supports_tx_param_setup() -> bool {
+        false
+    }
+
+    fn max_mac_payload_size(dr: DR, _dwell_time: bool) -> Result<usize, Error> {
+        match dr {
+            DR::_1 => Ok(31),
+            DR::_2 => Ok(94),
+            DR::_3 => Ok(192),
+            DR::_4 | DR::_5 | DR::_6 | DR::_7 => Ok(250),
+            _ => Err(Error::DataRateNotSupported(dr)),
+        }
+    }
+
+    fn beacon_frequency(_channel: u8) -> u32 {
+        CN470Plan::A20.rx2_frequency()
+    }
+
+    fn beacon_data_rate() -> DR {
+        DR::_2
+    }
+
+    fn beacon_rfu_lengths() -> (usize, usize) {
+        (3, 1)
+    }
+
+    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
+        match tx_power {
+            0..=7 => {
+                let next_dbm = max_eirp.checked_sub_unsigned(tx_power * 2);
+                if next_dbm.is_none() {
+                    Err(Error::InvalidTxPower)
+                } else {
+                    Ok(next_dbm)
+                }
+            }
+            15 => Ok(cur_dbm),
+            _ => Err(Error::InvalidTxPower),
+        }
+    }
+
+    fn get_rx1_dr(ul_dr: DR, rx1_dr_offset: u8) -> Result<DR, super::Error> {
+        if rx1_dr_offset > 5 {
+            return Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset));
+        }
+        let dl_dr_matrix = [
+            [DR::_1, DR::_1, DR::_1, DR::_1, DR::_1, DR::_1],
+            [DR::_2, DR::_1, DR::_1, DR::_1, DR::_1, DR::_1],
+            [DR::_3, DR::_2, DR::_1, DR::_1, DR::_1, DR::_1],
+            [DR::_4, DR::_3, DR::_2, DR::_1, DR::_1, DR::_1],
+            [DR::_5, DR::_4, DR::_3, DR::_2, DR::_1, DR::_1],
+            [DR::_6, DR::_5, DR::_4, DR::_3, DR::_2, DR::_1],
+        ];
+        match ul_dr {
+            DR::_1 => Ok(dl_dr_matrix[0][rx1_dr_offset as usize]),
+            DR::_2 => Ok(dl_dr_matrix[1][rx1_dr_offset as usize]),
+            DR::_3 => Ok(dl_dr_matrix[2][rx1_dr_offset as usize]),
+            DR::_4 => Ok(dl_dr_matrix[3][rx1_dr_offset as usize]),
+            DR::_5 => Ok(dl_dr_matrix[4][rx1_dr_offset as usize]),
+            DR::_6 => Ok(dl_dr_matrix[5][rx1_dr_offset as usize]),
+            _ => Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset)),
+        }
+    }
+}
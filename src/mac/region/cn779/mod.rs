@@ -0,0 +1,182 @@
+//! Processing for the CN779 region, which uses a dynamic channel plan.
+
+use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
+
+use super::channel_plan::dynamic::DynamicChannel;
+use super::Error;
+use crate::device::types::Datarate;
+use crate::mac::duty_cycle::SubBand;
+use crate::mac::types::{Frame, DR};
+
+const JOIN_CHANNELS: [u32; 3] = [779_500_000, 779_700_000, 779_900_000];
+// 1% duty cycle across the band
+const SUB_BANDS: [SubBand; 1] = [SubBand::new(779_500_000, 786_500_000, 100)];
+
+/// Specific processing for the CN779 region.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CN779;
+impl crate::mac::Region for CN779 {
+    fn default_channels(_is_uplink: bool) -> usize {
+        3
+    }
+    fn channel_from_list(_channel_id: usize) -> Result<DynamicChannel, Error> {
+        Err(Error::UnsupportedChannelListForRegion)
+    }
+    fn mandatory_frequency(index: usize, _is_uplink: bool) -> u32 {
+        JOIN_CHANNELS[index]
+    }
+    fn mandatory_ul_data_rate_range(_index: usize) -> (DR, DR) {
+        (DR::_0, DR::_5)
+    }
+    fn ul_data_rate_range() -> (DR, DR) {
+        (DR::_0, DR::_5)
+    }
+    fn min_frequency() -> u32 {
+        779500000
+    }
+    fn max_frequency() -> u32 {
+        786500000
+    }
+    fn default_rx2_frequency() -> u32 {
+        786000000
+    }
+    fn default_rx2_data_rate() -> DR {
+        DR::_0
+    }
+    fn default_data_rate() -> DR {
+        DR::_0
+    }
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        _frame: Frame,
+        _ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        if dr.in_range(CN779::ul_data_rate_range()) {
+            dr
+        } else {
+            CN779::default_data_rate()
+        }
+    }
+
+    fn default_rx1_data_rate_offset() -> u8 {
+        0
+    }
+
+    fn convert_data_rate(dr: DR) -> Result<Datarate, super::Error> {
+        match dr {
+            DR::_0 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_12,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_1 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_11,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_2 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_10,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_3 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_9,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_4 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_8,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_5 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_125KHz,
+            }),
+            DR::_6 => Ok(Datarate::LoRa {
+                spreading_factor: SpreadingFactor::_7,
+                bandwidth: Bandwidth::_250KHz,
+            }),
+            DR::_7 => Ok(Datarate::Fsk { bit_rate: 50_000 }),
+            _ => Err(super::Error::DataRateNotSupported(dr)),
+        }
+    }
+
+    fn next_adr_data_rate(current_dr: Option<DR>) -> Option<DR> {
+        match current_dr {
+            Some(DR::_0) => None,
+            Some(DR::_1) => Some(DR::_0),
+            Some(DR::_2) => Some(DR::_1),
+            Some(DR::_3) => Some(DR::_2),
+            Some(DR::_4) => Some(DR::_3),
+            Some(DR::_5) => Some(DR::_4),
+            Some(DR::_6) => Some(DR::_5),
+            Some(DR::_7) => Some(DR::_6),
+            _ => Some(DR::_0),
+        }
+    }
+
+    fn default_coding_rate() -> CodingRate {
+        CodingRate::_4_5
+    }
+    fn max_eirp() -> i8 {
+        12
+    }
+    fn supports_tx_param_setup() -> bool {
+        false
+    }
+    fn max_mac_payload_size(dr: DR, _dwell_time: bool) -> Result<usize, Error> {
+        match dr {
+            DR::_0 | DR::_1 | DR::_2 => Ok(59),
+            DR::_3 => Ok(123),
+            DR::_4 | DR::_5 | DR::_6 | DR::_7 => Ok(250),
+            _ => Err(Error::DataRateNotSupported(dr)),
+        }
+    }
+    fn sub_bands() -> &'static [SubBand] {
+        &SUB_BANDS
+    }
+    fn beacon_frequency(_channel: u8) -> u32 {
+        785000000
+    }
+    fn beacon_data_rate() -> DR {
+        DR::_3
+    }
+
+    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
+        match tx_power {
+            0..=5 => {
+                let next_dbm = max_eirp.checked_sub_unsigned(tx_power * 2);
+                if next_dbm.is_none() {
+                    Err(Error::InvalidTxPower)
+                } else {
+                    Ok(next_dbm)
+                }
+            }
+            15 => Ok(cur_dbm),
+            _ => Err(Error::InvalidTxPower),
+        }
+    }
+
+    fn get_rx1_dr(ul_dr: DR, rx1_dr_offset: u8) -> Result<DR, super::Error> {
+        if rx1_dr_offset > 5 {
+            return Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset));
+        }
+        let dl_dr_matrix = [
+            [DR::_0, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_1, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_2, DR::_1, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_3, DR::_2, DR::_1, DR::_0, DR::_0, DR::_0],
+            [DR::_4, DR::_3, DR::_2, DR::_1, DR::_0, DR::_0],
+            [DR::_5, DR::_4, DR::_3, DR::_2, DR::_1, DR::_0],
+            [DR::_6, DR::_5, DR::_4, DR::_3, DR::_2, DR::_1],
+            [DR::_7, DR::_6, DR::_5, DR::_4, DR::_3, DR::_2],
+        ];
+        match ul_dr {
+            DR::_0 => Ok(dl_dr_matrix[0][rx1_dr_offset as usize]),
+            DR::_1 => Ok(dl_dr_matrix[1][rx1_dr_offset as usize]),
+            DR::_2 => Ok(dl_dr_matrix[2][rx1_dr_offset as usize]),
+            DR::_3 => Ok(dl_dr_matrix[3][rx1_dr_offset as usize]),
+            DR::_4 => Ok(dl_dr_matrix[4][rx1_dr_offset as usize]),
+            DR::_5 => Ok(dl_dr_matrix[5][rx1_dr_offset as usize]),
+            DR::_6 => Ok(dl_dr_matrix[6][rx1_dr_offset as usize]),
+            DR::_7 => Ok(dl_dr_matrix[7][rx1_dr_offset as usize]),
+            _ => Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset)),
+        }
+    }
+}
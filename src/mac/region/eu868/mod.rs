@@ -4,10 +4,22 @@ use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
 
 use super::channel_plan::dynamic::{DynamicChannel, DynamicChannelPlan};
 use super::Error;
-use crate::device::types::Datarate;
+use crate::device::types::{Datarate, LrFhssCodingRate};
+use crate::mac::duty_cycle::SubBand;
 use crate::mac::types::{Frame, DR};
 
+// LR-FHSS hopping grid
+const LR_FHSS_GRID: u32 = 3_906;
 const JOIN_CHANNELS: [u32; 3] = [868_100_000, 868_300_000, 868_500_000];
+// ETSI EN 300 220 sub-bands and their duty cycle limits
+const SUB_BANDS: [SubBand; 6] = [
+    SubBand::new(863_000_000, 865_000_000, 1000),
+    SubBand::new(865_000_000, 868_000_000, 100),
+    SubBand::new(868_000_000, 868_600_000, 100),
+    SubBand::new(868_700_000, 869_200_000, 1000),
+    SubBand::new(869_400_000, 869_650_000, 10),
+    SubBand::new(869_700_000, 870_000_000, 100),
+];
 
 /// Specific processing for the EU868 region.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -28,6 +40,10 @@ impl crate::mac::Region for EU868 {
     fn ul_data_rate_range() -> (DR, DR) {
         (DR::_0, DR::_5)
     }
+    fn max_ul_data_rate() -> DR {
+        // DR6, FSK at DR7 and LR-FHSS at DR8 through DR11
+        DR::_11
+    }
     fn min_frequency() -> u32 {
         863000000
     }
@@ -43,8 +59,12 @@ impl crate::mac::Region for EU868 {
     fn default_data_rate() -> DR {
         DR::_0
     }
-    fn override_ul_data_rate_if_necessary(dr: DR, _frame: Frame, _ul_frequency: u32) -> DR {
-        if dr.in_range(EU868::ul_data_rate_range()) {
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        _frame: Frame,
+        _ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        if dr.in_range((EU868::ul_data_rate_range().0, EU868::max_ul_data_rate())) {
             dr
         } else {
             EU868::default_data_rate()
@@ -57,34 +77,55 @@ impl crate::mac::Region for EU868 {
 
     fn convert_data_rate(dr: DR) -> Result<Datarate, super::Error> {
         match dr {
-            DR::_0 => Ok(Datarate {
+            DR::_0 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_12,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_1 => Ok(Datarate {
+            DR::_1 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_11,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_2 => Ok(Datarate {
+            DR::_2 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_10,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_3 => Ok(Datarate {
+            DR::_3 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_9,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_4 => Ok(Datarate {
+            DR::_4 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_8,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_5 => Ok(Datarate {
+            DR::_5 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_7,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_6 => Ok(Datarate {
+            DR::_6 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_7,
                 bandwidth: Bandwidth::_250KHz,
             }),
+            DR::_7 => Ok(Datarate::Fsk { bit_rate: 50_000 }),
+            DR::_8 => Ok(Datarate::LrFhss {
+                coding_rate: LrFhssCodingRate::_1_3,
+                bandwidth: 137_000,
+                grid: LR_FHSS_GRID,
+            }),
+            DR::_9 => Ok(Datarate::LrFhss {
+                coding_rate: LrFhssCodingRate::_2_3,
+                bandwidth: 137_000,
+                grid: LR_FHSS_GRID,
+            }),
+            DR::_10 => Ok(Datarate::LrFhss {
+                coding_rate: LrFhssCodingRate::_1_3,
+                bandwidth: 336_000,
+                grid: LR_FHSS_GRID,
+            }),
+            DR::_11 => Ok(Datarate::LrFhss {
+                coding_rate: LrFhssCodingRate::_2_3,
+                bandwidth: 336_000,
+                grid: LR_FHSS_GRID,
+            }),
             _ => Err(super::Error::DataRateNotSupported(dr)),
         }
     }
@@ -98,6 +139,9 @@ impl crate::mac::Region for EU868 {
             Some(DR::_4) => Some(DR::_3),
             Some(DR::_5) => Some(DR::_4),
             Some(DR::_6) => Some(DR::_5),
+            Some(DR::_7) => Some(DR::_6),
+            Some(DR::_9) => Some(DR::_8),
+            Some(DR::_11) => Some(DR::_10),
             _ => Some(DR::_0),
         }
     }
@@ -111,6 +155,25 @@ impl crate::mac::Region for EU868 {
     fn supports_tx_param_setup() -> bool {
         false
     }
+    fn max_mac_payload_size(dr: DR, _dwell_time: bool) -> Result<usize, Error> {
+        match dr {
+            DR::_0 | DR::_1 | DR::_2 => Ok(59),
+            DR::_3 => Ok(123),
+            DR::_4 | DR::_5 | DR::_6 | DR::_7 => Ok(250),
+            DR::_8 | DR::_10 => Ok(58),
+            DR::_9 | DR::_11 => Ok(123),
+            _ => Err(Error::DataRateNotSupported(dr)),
+        }
+    }
+    fn sub_bands() -> &'static [SubBand] {
+        &SUB_BANDS
+    }
+    fn beacon_frequency(_channel: u8) -> u32 {
+        869525000
+    }
+    fn beacon_data_rate() -> DR {
+        DR::_3
+    }
 
     fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
         match tx_power {
@@ -138,6 +201,10 @@ impl crate::mac::Region for EU868 {
             [DR::_3, DR::_2, DR::_1, DR::_0, DR::_0, DR::_0],
             [DR::_4, DR::_3, DR::_2, DR::_1, DR::_0, DR::_0],
             [DR::_5, DR::_4, DR::_3, DR::_2, DR::_1, DR::_0],
+            [DR::_6, DR::_5, DR::_4, DR::_3, DR::_2, DR::_1],
+            [DR::_7, DR::_6, DR::_5, DR::_4, DR::_3, DR::_2],
+            [DR::_1, DR::_0, DR::_0, DR::_0, DR::_0, DR::_0],
+            [DR::_2, DR::_1, DR::_0, DR::_0, DR::_0, DR::_0],
         ];
         match ul_dr {
             DR::_0 => Ok(dl_dr_matrix[0][rx1_dr_offset as usize]),
@@ -146,6 +213,10 @@ impl crate::mac::Region for EU868 {
             DR::_3 => Ok(dl_dr_matrix[3][rx1_dr_offset as usize]),
             DR::_4 => Ok(dl_dr_matrix[4][rx1_dr_offset as usize]),
             DR::_5 => Ok(dl_dr_matrix[5][rx1_dr_offset as usize]),
+            DR::_6 => Ok(dl_dr_matrix[6][rx1_dr_offset as usize]),
+            DR::_7 => Ok(dl_dr_matrix[7][rx1_dr_offset as usize]),
+            DR::_8 | DR::_10 => Ok(dl_dr_matrix[8][rx1_dr_offset as usize]),
+            DR::_9 | DR::_11 => Ok(dl_dr_matrix[9][rx1_dr_offset as usize]),
             _ => Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset)),
         }
     }
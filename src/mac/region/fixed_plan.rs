@@ -0,0 +1,75 @@
+//! Processing shared by the US915 and AU915 regions, whose fixed channel plans of 64 125 KHz and 8 500 KHz uplink
+//! channels with 8 500 KHz downlink channels differ only in the uplink frequencies and data rates.
+
+use lora_modulation::{Bandwidth, SpreadingFactor};
+
+use super::Error;
+use crate::device::types::Datarate;
+use crate::mac::types::{Frame, DR};
+
+/// Get the number of uplink or downlink channels.
+pub(crate) fn default_channels(is_uplink: bool) -> usize {
+    if is_uplink {
+        72
+    } else {
+        8
+    }
+}
+
+/// Get the downlink frequency based on channel index, which is also that of the Class B beacon channel.
+pub(crate) fn downlink_frequency(index: usize) -> u32 {
+    // downstream: 8 (923.3 to 927.5 [+ by 0.6])
+    (923_300_000 + (600_000 * index)) as u32
+}
+
+/// Override the uplink data rate of a join request for the data rate range of the selected channel, using the
+/// default data rate on the 125 KHz channels and the lowest 500 KHz data rate on the others.  Data frames keep their
+/// data rate, so channels whose range excludes it are not used.
+pub(crate) fn override_ul_data_rate(
+    dr: DR,
+    frame: Frame,
+    ul_data_rate_range: (DR, DR),
+    default_data_rate: DR,
+) -> DR {
+    if frame == Frame::Data {
+        dr
+    } else if default_data_rate.in_range(ul_data_rate_range) {
+        default_data_rate
+    } else {
+        ul_data_rate_range.0
+    }
+}
+
+/// Convert the downlink data rates DR8 through DR13 to spreading factor and bandwidth.
+pub(crate) fn convert_downlink_data_rate(dr: DR) -> Result<Datarate, Error> {
+    let spreading_factor = match dr {
+        DR::_8 => SpreadingFactor::_12,
+        DR::_9 => SpreadingFactor::_11,
+        DR::_10 => SpreadingFactor::_10,
+        DR::_11 => SpreadingFactor::_9,
+        DR::_12 => SpreadingFactor::_8,
+        DR::_13 => SpreadingFactor::_7,
+        _ => return Err(Error::DataRateNotSupported(dr)),
+    };
+    Ok(Datarate::LoRa { spreading_factor, bandwidth: Bandwidth::_500KHz })
+}
+
+/// Based on the LinkADRReq packet, modify the configured transmission power in steps of 2 dB below the maximum EIRP.
+pub(crate) fn modify_dbm(
+    tx_power: u8,
+    cur_dbm: Option<i8>,
+    max_eirp: i8,
+) -> Result<Option<i8>, Error> {
+    match tx_power {
+        0..=14 => {
+            let next_dbm = max_eirp.checked_sub_unsigned(tx_power * 2);
+            if next_dbm.is_none() {
+                Err(Error::InvalidTxPower)
+            } else {
+                Ok(next_dbm)
+            }
+        }
+        15 => Ok(cur_dbm),
+        _ => Err(Error::InvalidTxPower),
+    }
+}
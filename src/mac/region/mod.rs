@@ -1,12 +1,13 @@
 //! Specification of functionality implemented for each supported LoRaWAN region.
 
-use lora_modulation::CodingRate;
+use lora_modulation::{BaseBandModulationParams, CodingRate};
 
-use crate::device::types::Datarate;
+use crate::device::types::{Datarate, LrFhssCodingRate};
 use crate::device::Device;
 
 use self::channel_plan::dynamic::DynamicChannel;
 
+use super::duty_cycle::SubBand;
 use super::types::{Frame, DR};
 pub mod channel_plan;
 
@@ -24,6 +25,7 @@ pub enum Error {
     InvalidCfListType,
     CommandNotImplementedForRegion,
     UnsupportedChannelListForRegion,
+    InvalidChannelPlanVariant,
 }
 impl<D> From<Error> for crate::Error<D>
 where
@@ -34,6 +36,17 @@ where
     }
 }
 
+/// Listen Before Talk parameters: a transmission may only start once the channel RSSI has stayed at or below
+/// the threshold for the sensing time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ListenBeforeTalk {
+    /// RSSI threshold in dBm.
+    pub rssi_threshold: i16,
+    /// Minimum carrier sense time in milliseconds.
+    pub sensing_time_ms: u32,
+}
+
 /// Specification of functionality to describe regional characteristics.
 pub trait Region {
     /// Get the number of default uplink or downlink channels for the region.
@@ -46,10 +59,17 @@ pub trait Region {
     fn mandatory_ul_data_rate_range(index: usize) -> (DR, DR);
     /// Get the uplink data rate range.
     fn ul_data_rate_range() -> (DR, DR);
+    /// Get the highest uplink data rate of the region, which may be above the uplink data rate range for data rates
+    /// only used on channels opened for them through NewChannelReq.
+    fn max_ul_data_rate() -> DR {
+        Self::ul_data_rate_range().1
+    }
     /// Get the default data rate for the region.
     fn default_data_rate() -> DR;
-    /// Override the uplink data rate based on region, frame type (join or data), and frequency.
-    fn override_ul_data_rate_if_necessary(dr: DR, frame: Frame, ul_frequency: u32) -> DR;
+    /// Override the uplink data rate based on region, frame type (join or data), and the uplink data rate range
+    /// of the selected channel.
+    fn override_ul_data_rate_if_necessary(dr: DR, frame: Frame, ul_data_rate_range: (DR, DR))
+        -> DR;
     /// Get the default coding rate for the region.
     fn default_coding_rate() -> CodingRate;
     /// Get the default RX2 frequency for the region.
@@ -95,7 +115,91 @@ pub trait Region {
     fn default_adr_ack_delay() -> u8 {
         32
     }
+    /// Get the time-on-air in microseconds of a LoRaWAN frame (PHYPayload) of `len` bytes sent at the given data
+    /// rate, with the 8 symbol preamble, explicit header and CRC used for uplinks.  The low data rate optimisation
+    /// is applied by the modulation parameters where the symbol duration requires it.  FSK frames add the
+    /// preamble, sync word, length byte and CRC to the PHYPayload.  LR-FHSS frames add the repeated headers to
+    /// the coded PHYPayload and CRC, sent in fragments at 488.28125 bits per second.
+    fn time_on_air_us(dr: DR, len: u8) -> Result<u32, Error> {
+        match Self::convert_data_rate(dr)? {
+            Datarate::LoRa { spreading_factor, bandwidth } => {
+                let bb = BaseBandModulationParams::new(
+                    spreading_factor,
+                    bandwidth,
+                    Self::default_coding_rate(),
+                );
+                Ok(bb.time_on_air_us(Some(8), true, len))
+            }
+            Datarate::Fsk { bit_rate } => {
+                let bits = (5 + 3 + 1 + len as u64 + 2) * 8;
+                Ok((bits * 1_000_000).div_ceil(bit_rate as u64) as u32)
+            }
+            Datarate::LrFhss { coding_rate, .. } => {
+                const HEADER_BITS: u32 = 114;
+                // 48 coded bits in each fragment of 50 bits
+                const FRAGMENT_BITS: u32 = 48;
+                // payload and CRC, with 6 tail bits
+                let bits = (len as u32 + 2) * 8 + 6;
+                let coded_bits = match coding_rate {
+                    LrFhssCodingRate::_1_3 => bits * 3,
+                    LrFhssCodingRate::_2_3 => bits * 3 / 2,
+                };
+                let mut payload_bits = coded_bits / FRAGMENT_BITS * (FRAGMENT_BITS + 2);
+                if coded_bits % FRAGMENT_BITS > 0 {
+                    payload_bits += coded_bits % FRAGMENT_BITS + 2;
+                }
+                // each bit lasts 2.048 ms
+                Ok((coding_rate.header_count() * HEADER_BITS + payload_bits) * 2048)
+            }
+        }
+    }
+    /// Is the 400 ms dwell time limit applied to uplinks or downlinks until a TXParamSetupReq says otherwise?
+    fn default_dwell_time(_is_uplink: bool) -> bool {
+        false
+    }
+    /// Get the lowest data rate used in RX1, with or without the downlink dwell time limit.
+    fn min_rx1_data_rate(_dwell_time: bool) -> DR {
+        DR::_0
+    }
+    /// Get the maximum MACPayload size (M) for the data rate, with or without the uplink dwell time limit.
+    fn max_mac_payload_size(dr: DR, dwell_time: bool) -> Result<usize, Error>;
+    /// Get the maximum FRMPayload size (N) for the data rate, with or without the uplink dwell time limit,
+    /// assuming no FOpts.
+    fn max_frm_payload_size(dr: DR, dwell_time: bool) -> Result<usize, Error> {
+        // FHDR without FOpts and FPort
+        Ok(Self::max_mac_payload_size(dr, dwell_time)? - 8)
+    }
+    /// Get the Listen Before Talk parameters if the region requires carrier sensing before each transmission.
+    fn lbt() -> Option<ListenBeforeTalk> {
+        None
+    }
+    /// Get the sub-bands whose duty cycle limits transmissions in the region.
+    fn sub_bands() -> &'static [SubBand] {
+        &[]
+    }
+    /// Get the Class B beacon frequency for the given beacon channel, also the default ping slot frequency.
+    fn beacon_frequency(channel: u8) -> u32;
+    /// Get the Class B beacon data rate for the region, also the default ping slot data rate.
+    fn beacon_data_rate() -> DR;
+    /// Get the number of channels over which the Class B beacon hops.
+    fn beacon_channels() -> u8 {
+        1
+    }
+    /// Get the lengths of the RFU fields preceding the beacon time and following the gateway-specific information.
+    fn beacon_rfu_lengths() -> (usize, usize) {
+        (2, 0)
+    }
 }
 
+pub mod any;
+pub mod as923;
+pub mod au915;
+pub mod cn470;
+pub mod cn779;
+pub mod eu433;
 pub mod eu868;
+mod fixed_plan;
+pub mod in865;
+pub mod kr920;
+pub mod ru864;
 pub mod us915;
@@ -3,20 +3,17 @@
 use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
 
 use super::channel_plan::dynamic::DynamicChannel;
-use super::Error;
-use crate::device::types::Datarate;
+use super::{fixed_plan, Error};
+use crate::device::types::{Datarate, LrFhssCodingRate};
 use crate::mac::types::{Frame, DR};
 
 /// Specific processing for the US915 region.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct US915;
 
 impl crate::mac::Region for US915 {
     fn default_channels(is_uplink: bool) -> usize {
-        if is_uplink {
-            72
-        } else {
-            8
-        }
+        fixed_plan::default_channels(is_uplink)
     }
 
     fn channel_from_list(_channel_id: usize) -> Result<DynamicChannel, Error> {
@@ -32,17 +29,16 @@ impl crate::mac::Region for US915 {
                 (903_000_000 + (1_600_000 * (index - 64))) as u32
             }
         } else {
-            // downstream: 8 (923.3 to 927.5 [+ by 0.6])
-            (923_300_000 + (600_000 * index)) as u32
+            fixed_plan::downlink_frequency(index)
         }
     }
 
     fn mandatory_ul_data_rate_range(index: usize) -> (DR, DR) {
-        // 64 125 KHz channels using DR0 through DR3 + 8 500 KHz channels using DR4
+        // 64 125 KHz channels using DR0 through DR3 + 8 500 KHz channels using DR4, or LR-FHSS at DR5 and DR6
         if index < 64 {
             (DR::_0, DR::_3)
         } else {
-            (DR::_4, DR::_4)
+            (DR::_4, DR::_6)
         }
     }
 
@@ -50,26 +46,22 @@ impl crate::mac::Region for US915 {
         (DR::_0, DR::_4)
     }
 
+    fn max_ul_data_rate() -> DR {
+        // LR-FHSS at DR5 and DR6
+        DR::_6
+    }
+
     fn default_data_rate() -> DR {
         DR::_0
     }
 
-    fn override_ul_data_rate_if_necessary(dr: DR, frame: Frame, ul_frequency: u32) -> DR {
-        // the 8 500KHz uplink channels always use DR4
-        for index in 0..8 {
-            if (902_300_000 + (200_000 * index)) == ul_frequency {
-                return DR::_4;
-            }
-        }
-
-        // adjust the DR for the 125KHz channels as necessary
-        if frame == Frame::Join {
-            DR::_0
-        } else if dr.in_range((DR::_0, DR::_3)) {
-            dr
-        } else {
-            US915::default_data_rate()
-        }
+    fn override_ul_data_rate_if_necessary(
+        dr: DR,
+        frame: Frame,
+        ul_data_rate_range: (DR, DR),
+    ) -> DR {
+        // join requests use DR0 on the 125 KHz channels and DR4 on the 500 KHz channels
+        fixed_plan::override_ul_data_rate(dr, frame, ul_data_rate_range, Self::default_data_rate())
     }
 
     fn default_coding_rate() -> CodingRate {
@@ -77,7 +69,7 @@ impl crate::mac::Region for US915 {
     }
 
     fn default_rx2_frequency() -> u32 {
-        923_300_000
+        fixed_plan::downlink_frequency(0)
     }
 
     fn default_rx2_data_rate() -> DR {
@@ -98,51 +90,37 @@ impl crate::mac::Region for US915 {
 
     fn convert_data_rate(dr: DR) -> Result<Datarate, super::Error> {
         match dr {
-            DR::_0 => Ok(Datarate {
+            DR::_0 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_10,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_1 => Ok(Datarate {
+            DR::_1 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_9,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_2 => Ok(Datarate {
+            DR::_2 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_8,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_3 => Ok(Datarate {
+            DR::_3 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_7,
                 bandwidth: Bandwidth::_125KHz,
             }),
-            DR::_4 => Ok(Datarate {
+            DR::_4 => Ok(Datarate::LoRa {
                 spreading_factor: SpreadingFactor::_8,
                 bandwidth: Bandwidth::_500KHz,
             }),
-            DR::_8 => Ok(Datarate {
-                spreading_factor: SpreadingFactor::_12,
-                bandwidth: Bandwidth::_500KHz,
+            DR::_5 => Ok(Datarate::LrFhss {
+                coding_rate: LrFhssCodingRate::_1_3,
+                bandwidth: 1_523_000,
+                grid: 25_390,
             }),
-            DR::_9 => Ok(Datarate {
-                spreading_factor: SpreadingFactor::_11,
-                bandwidth: Bandwidth::_500KHz,
-            }),
-            DR::_10 => Ok(Datarate {
-                spreading_factor: SpreadingFactor::_10,
-                bandwidth: Bandwidth::_500KHz,
-            }),
-            DR::_11 => Ok(Datarate {
-                spreading_factor: SpreadingFactor::_9,
-                bandwidth: Bandwidth::_500KHz,
-            }),
-            DR::_12 => Ok(Datarate {
-                spreading_factor: SpreadingFactor::_8,
-                bandwidth: Bandwidth::_500KHz,
+            DR::_6 => Ok(Datarate::LrFhss {
+                coding_rate: LrFhssCodingRate::_2_3,
+                bandwidth: 1_523_000,
+                grid: 25_390,
             }),
-            DR::_13 => Ok(Datarate {
-                spreading_factor: SpreadingFactor::_7,
-                bandwidth: Bandwidth::_500KHz,
-            }),
-            _ => Err(super::Error::DataRateNotSupported(dr)),
+            _ => fixed_plan::convert_downlink_data_rate(dr),
         }
     }
 
@@ -169,6 +147,8 @@ impl crate::mac::Region for US915 {
             [DR::_12, DR::_11, DR::_10, DR::_9],
             [DR::_13, DR::_12, DR::_11, DR::_10],
             [DR::_13, DR::_13, DR::_12, DR::_11],
+            [DR::_10, DR::_9, DR::_8, DR::_8],
+            [DR::_11, DR::_10, DR::_9, DR::_8],
         ];
         match ul_dr {
             DR::_0 => Ok(dl_dr_matrix[0][rx1_dr_offset as usize]),
@@ -176,6 +156,8 @@ impl crate::mac::Region for US915 {
             DR::_2 => Ok(dl_dr_matrix[2][rx1_dr_offset as usize]),
             DR::_3 => Ok(dl_dr_matrix[3][rx1_dr_offset as usize]),
             DR::_4 => Ok(dl_dr_matrix[4][rx1_dr_offset as usize]),
+            DR::_5 => Ok(dl_dr_matrix[5][rx1_dr_offset as usize]),
+            DR::_6 => Ok(dl_dr_matrix[6][rx1_dr_offset as usize]),
             _ => Err(super::Error::UnsupportedRx1DROffset(ul_dr, rx1_dr_offset)),
         }
     }
@@ -184,21 +166,42 @@ impl crate::mac::Region for US915 {
         false
     }
 
-    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
-        match tx_power {
-            0..=14 => {
-                let next_dbm = max_eirp.checked_sub_unsigned(tx_power * 2);
-                if next_dbm.is_none() {
-                    Err(Error::InvalidTxPower)
-                } else {
-                    Ok(next_dbm)
-                }
-            }
-            15 => Ok(cur_dbm),
-            _ => Err(Error::InvalidTxPower),
+    fn max_mac_payload_size(dr: DR, _dwell_time: bool) -> Result<usize, Error> {
+        match dr {
+            DR::_0 => Ok(19),
+            DR::_1 => Ok(61),
+            DR::_2 => Ok(133),
+            DR::_3 | DR::_4 => Ok(250),
+            DR::_5 => Ok(58),
+            DR::_6 => Ok(133),
+            DR::_8 => Ok(61),
+            DR::_9 => Ok(137),
+            DR::_10 | DR::_11 | DR::_12 | DR::_13 => Ok(250),
+            _ => Err(Error::DataRateNotSupported(dr)),
         }
     }
 
+    fn beacon_frequency(channel: u8) -> u32 {
+        // the same as the downstream channels
+        fixed_plan::downlink_frequency(channel as usize)
+    }
+
+    fn beacon_data_rate() -> DR {
+        DR::_8
+    }
+
+    fn beacon_channels() -> u8 {
+        8
+    }
+
+    fn beacon_rfu_lengths() -> (usize, usize) {
+        (5, 3)
+    }
+
+    fn modify_dbm(tx_power: u8, cur_dbm: Option<i8>, max_eirp: i8) -> Result<Option<i8>, Error> {
+        fixed_plan::modify_dbm(tx_power, cur_dbm, max_eirp)
+    }
+
     fn default_rx1_data_rate_offset() -> u8 {
         0
     }
@@ -0,0 +1,111 @@
+//! Network time synchronisation through DeviceTimeAns, mapping the local time reported by
+//! [`Timer::now`](crate::device::timer::Timer::now) to GPS time.
+
+/// Minimum local time in milliseconds between the synchronisations used to estimate the drift, long enough for
+/// the 1/256 s resolution of DeviceTimeAns to allow an estimate within about 10 ppm.
+pub const MIN_DRIFT_INTERVAL_MS: u64 = 1000 * 60 * 60;
+
+/// Convert the time given by a DeviceTimeAns into milliseconds since the GPS epoch.
+pub(crate) fn device_time_ms(seconds: u32, nano_seconds: u32) -> u64 {
+    seconds as u64 * 1000 + nano_seconds as u64 / 1_000_000
+}
+
+/// Local time and GPS time, in milliseconds, at the same instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct SyncPoint {
+    local_ms: u64,
+    gps_ms: u64,
+}
+
+/// Mapping from local time to GPS time, updated with each DeviceTimeAns and corrected for the drift of the local
+/// clock measured across synchronisations.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeSync {
+    last: Option<SyncPoint>,
+    drift_reference: Option<SyncPoint>,
+    drift: Option<f32>,
+}
+
+impl TimeSync {
+    /// Record that the GPS time was `gps_ms` when the local time was `local_ms`, such as at the end of the uplink
+    /// carrying the DeviceTimeReq.
+    pub fn synchronise(&mut self, local_ms: u64, gps_ms: u64) {
+        let sync_point = SyncPoint { local_ms, gps_ms };
+        match self.drift_reference {
+            Some(reference) if local_ms >= reference.local_ms + MIN_DRIFT_INTERVAL_MS => {
+                let local_elapsed = (local_ms - reference.local_ms) as i64;
+                let gps_elapsed = gps_ms as i64 - reference.gps_ms as i64;
+                self.drift = Some((gps_elapsed - local_elapsed) as f32 / local_elapsed as f32);
+                self.drift_reference = Some(sync_point);
+            }
+            // restart the measurement if the local time went backwards, as after a power cycle
+            Some(reference) if local_ms < reference.local_ms => {
+                self.drift_reference = Some(sync_point)
+            }
+            Some(_) => {}
+            None => self.drift_reference = Some(sync_point),
+        }
+        self.last = Some(sync_point);
+    }
+
+    /// Get the GPS time in milliseconds at the local time `local_ms`, or None before the first synchronisation.
+    pub fn now_gps(&self, local_ms: u64) -> Option<u64> {
+        let last = self.last?;
+        let local_elapsed = local_ms as i64 - last.local_ms as i64;
+        let correction = self.drift.map_or(0, |drift| (local_elapsed as f32 * drift) as i64);
+        Some((last.gps_ms as i64 + local_elapsed + correction).max(0) as u64)
+    }
+
+    /// Get the rate at which GPS time runs ahead of the local time, in parts per million, once measured.
+    pub fn drift_ppm(&self) -> Option<f32> {
+        self.drift.map(|drift| drift * 1_000_000.0)
+    }
+
+    /// Has a DeviceTimeAns been received?
+    pub fn is_synchronised(&self) -> bool {
+        self.last.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_time() {
+        assert_eq!(device_time_ms(1, 500_000_000), 1_500);
+        assert_eq!(device_time_ms(1_000_000_000, 3_906_250), 1_000_000_000_003);
+    }
+
+    #[test]
+    fn synchronise() {
+        let mut time_sync = TimeSync::default();
+        assert_eq!(time_sync.now_gps(1_000), None);
+        time_sync.synchronise(3_000, 1_000_000_000);
+        assert_eq!(time_sync.now_gps(4_200), Some(1_000_001_200));
+        assert_eq!(time_sync.drift_ppm(), None);
+
+        // too soon to estimate the drift
+        time_sync.synchronise(63_000, 1_000_060_010);
+        assert_eq!(time_sync.now_gps(64_000), Some(1_000_061_010));
+        assert_eq!(time_sync.drift_ppm(), None);
+
+        // the local clock runs 50 ppm slow
+        let hour = MIN_DRIFT_INTERVAL_MS;
+        time_sync.synchronise(3_000 + hour, 1_000_000_000 + hour + 180);
+        assert!((time_sync.drift_ppm().unwrap() - 50.0).abs() < 0.5);
+        assert_eq!(time_sync.now_gps(3_000 + 2 * hour), Some(1_000_000_000 + 2 * hour + 360));
+    }
+
+    #[test]
+    fn restart() {
+        let mut time_sync = TimeSync::default();
+        time_sync.synchronise(10_000_000, 1_000_000_000);
+        time_sync.synchronise(5_000, 1_000_100_000);
+        assert_eq!(time_sync.now_gps(6_000), Some(1_000_101_000));
+        time_sync.synchronise(5_000 + MIN_DRIFT_INTERVAL_MS, 1_000_100_000 + MIN_DRIFT_INTERVAL_MS);
+        assert_eq!(time_sync.drift_ppm(), Some(0.0));
+    }
+}
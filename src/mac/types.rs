@@ -3,6 +3,10 @@
 use encoding::default_crypto::DefaultFactory;
 use encoding::keys::{AppEui, AppKey, AppSKey, DevEui, NwkSKey};
 use encoding::parser::{DecryptedJoinAcceptPayload, DevAddr, DevNonce};
+use lora_phy::mod_params::PacketParams;
+
+use super::duty_cycle::JoinBackOff;
+use super::region::any::RegionId;
 
 pub(crate) struct RxWindows {
     pub(crate) rx1_open: u16,
@@ -17,6 +21,17 @@ impl RxWindows {
     }
 }
 
+/// Time in milliseconds an FSK receive window stays open without detecting a preamble, allowing for the same
+/// timing error as LoRa windows.
+pub(crate) const FSK_RX_WINDOW_MS: u32 = 100;
+
+/// A receive window prepared on the radio, or left to [`Device::fsk_rx`](crate::device::Device::fsk_rx) for FSK
+/// with the window duration in milliseconds, None for continuous reception.
+pub(crate) enum PreparedRx {
+    LoRa(PacketParams),
+    Fsk(Option<u32>),
+}
+
 /// Basic send/receive properties.
 #[derive(Debug, PartialEq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -29,6 +44,12 @@ pub struct Configuration {
     pub(crate) rx2_data_rate: Option<DR>,
     pub(crate) rx2_frequency: Option<u32>,
     pub(crate) number_of_transmissions: u8,
+    pub(crate) uplink_dwell_time: Option<bool>,
+    pub(crate) downlink_dwell_time: Option<bool>,
+    pub(crate) max_eirp: Option<i8>,
+    pub(crate) join_back_off: JoinBackOff,
+    /// The region selected at runtime which the channel plan of the `Mac` was created for, if any.
+    pub(crate) region: Option<RegionId>,
 }
 
 impl Default for Configuration {
@@ -42,10 +63,23 @@ impl Default for Configuration {
             rx2_data_rate: None,
             rx2_frequency: None,
             number_of_transmissions: 1,
+            uplink_dwell_time: None,
+            downlink_dwell_time: None,
+            max_eirp: None,
+            join_back_off: Default::default(),
+            region: None,
         }
     }
 }
 
+impl Configuration {
+    /// Get the region selected at runtime which was persisted with the configuration, if any, to select again
+    /// through [`AnyRegion::select`](crate::mac::region::any::AnyRegion::select) after a power cycle.
+    pub fn region(&self) -> Option<RegionId> {
+        self.region
+    }
+}
+
 /// Identification properties used to enable communication with a network server.
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -81,6 +115,8 @@ pub struct Session {
     pub(crate) fcnt_up: u32,
     pub(crate) fcnt_down: u32,
     pub(crate) adr_ack_cnt: u8,
+    pub(crate) channel_plan_variant: Option<u8>,
+    pub(crate) channel_mask: Option<[u8; 9]>,
 }
 impl Session {
     /// Creation.
@@ -104,7 +140,16 @@ impl Session {
 
     /// Creation.
     pub fn new(nwkskey: NwkSKey, appskey: AppSKey, devaddr: DevAddr<[u8; 4]>) -> Self {
-        Self { nwkskey, appskey, devaddr, fcnt_up: 0, fcnt_down: 0, adr_ack_cnt: 0 }
+        Self {
+            nwkskey,
+            appskey,
+            devaddr,
+            fcnt_up: 0,
+            fcnt_down: 0,
+            adr_ack_cnt: 0,
+            channel_plan_variant: None,
+            channel_mask: None,
+        }
     }
 
     /// Get the network session key.
@@ -146,7 +191,8 @@ impl Session {
 
 /// Basic send/receive properties persisted in non-volatile storage for
 /// continuity across power-on cycles.
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// [`Storable::encode`] and [`Storable::decode`] provide a versioned, CRC protected encoding.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[allow(missing_docs)]
@@ -156,6 +202,26 @@ pub struct Storable {
     pub rx2_data_rate: Option<DR>,
     pub rx2_frequency: Option<u32>,
     pub dev_nonce: u16,
+    pub nwkskey: Option<[u8; 16]>,
+    pub appskey: Option<[u8; 16]>,
+    pub devaddr: Option<[u8; 4]>,
+    pub fcnt_up: u32,
+    pub fcnt_down: u32,
+    pub adr_ack_cnt: u8,
+    pub tx_power: Option<i8>,
+    pub tx_data_rate: Option<DR>,
+    pub number_of_transmissions: u8,
+    pub uplink_dwell_time: Option<bool>,
+    pub downlink_dwell_time: Option<bool>,
+    pub max_eirp: Option<i8>,
+    pub channel_plan_variant: Option<u8>,
+    /// Uplink channel mask of a fixed channel plan, one bit per channel.
+    pub channel_mask: Option<[u8; 9]>,
+    pub region: Option<u8>,
+    /// Seconds elapsed since the first join-request of an unfinished join procedure.
+    pub join_elapsed: Option<u32>,
+    /// Seconds before the next join-request is allowed.
+    pub join_time_off: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -227,6 +293,19 @@ pub enum MType {
     RFU,
     Proprietary,
 }
+/// LoRaWAN end device class, determining when the end device listens for downlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Class {
+    /// Listen only in the RX1 and RX2 windows following an uplink.
+    #[default]
+    A,
+    /// Additionally listen in ping slots scheduled relative to the network beacon.
+    B,
+    /// Additionally listen continuously on the RX2 frequency and data rate between uplinks.
+    C,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(missing_docs)]
 pub enum Frame {